@@ -0,0 +1,193 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Attributes Module (`attrs`)
+//!
+//! This internal module compares the attributes of file system items beyond
+//! size and modification time: permission bits, owner and group, extended
+//! attributes and access control lists (ACLs).
+//!
+//! POSIX ACLs are stored in the extended attributes
+//! `system.posix_acl_access` and `system.posix_acl_default`, so they are
+//! read along with the other extended attributes, but reported separately.
+//! Extended attributes which can't be read, e.g. for lack of permission or
+//! support by the file system, are not regarded as different.
+
+use std::{fs::Metadata, io, path::Path};
+
+use tokio::task;
+
+use crate::fsitem::FSItem;
+
+/// The attributes differing between the items on both sides of a
+/// comparison.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AttrDiff {
+    /// The permission bits, including the set-user-ID, set-group-ID and
+    /// sticky bits, differ.
+    pub mode: bool,
+    /// The owners differ.
+    pub owner: bool,
+    /// The groups differ.
+    pub group: bool,
+    /// The extended attributes, apart from ACLs, differ.
+    pub xattrs: bool,
+    /// The access control lists differ.
+    pub acl: bool,
+    /// The attributes of entries inside of the directories differ.
+    pub entries: bool,
+}
+
+impl AttrDiff {
+    /// Returns `true` if no attribute differs.
+    pub const fn is_empty(&self) -> bool {
+        !(self.mode
+            || self.owner
+            || self.group
+            || self.xattrs
+            || self.acl
+            || self.entries)
+    }
+}
+
+/// Names of the extended attributes holding POSIX ACLs.
+#[cfg(unix)]
+const ACL_NAMES: [&str; 2] =
+    ["system.posix_acl_access", "system.posix_acl_default"];
+
+/// Compares the attributes of the items `left` and `right`, which must both
+/// exist.
+pub(crate) async fn cmp_attributes(
+    left: &FSItem,
+    right: &FSItem,
+) -> io::Result<AttrDiff> {
+    let (Some(left_meta), Some(right_meta)) =
+        (left.metadata(), right.metadata())
+    else {
+        return Ok(AttrDiff::default());
+    };
+    let mut attr_diff = cmp_metadata(left_meta, right_meta);
+    let left_path = left.path().clone();
+    let right_path = right.path().clone();
+    let (xattrs, acl) =
+        task::spawn_blocking(move || cmp_xattrs(&left_path, &right_path))
+            .await
+            .map_err(io::Error::other)?;
+    attr_diff.xattrs = xattrs;
+    attr_diff.acl = acl;
+    Ok(attr_diff)
+}
+
+/// Compares permission bits, owner and group given by `left_meta` and
+/// `right_meta`.
+#[cfg(unix)]
+fn cmp_metadata(left_meta: &Metadata, right_meta: &Metadata) -> AttrDiff {
+    use std::os::unix::fs::MetadataExt;
+    AttrDiff {
+        mode: left_meta.mode() & 0o7777 != right_meta.mode() & 0o7777,
+        owner: left_meta.uid() != right_meta.uid(),
+        group: left_meta.gid() != right_meta.gid(),
+        ..Default::default()
+    }
+}
+
+/// Compares the permissions given by `left_meta` and `right_meta`.
+#[cfg(not(unix))]
+fn cmp_metadata(left_meta: &Metadata, right_meta: &Metadata) -> AttrDiff {
+    AttrDiff {
+        mode: left_meta.permissions().readonly()
+            != right_meta.permissions().readonly(),
+        ..Default::default()
+    }
+}
+
+/// Compares the extended attributes of the items at `left` and `right`,
+/// not following symbolic links, and returns whether the ordinary ones and
+/// whether the ACLs differ.
+#[cfg(unix)]
+fn cmp_xattrs(left: &Path, right: &Path) -> (bool, bool) {
+    let (Some(left_attrs), Some(right_attrs)) =
+        (read_xattrs(left), read_xattrs(right))
+    else {
+        return (false, false);
+    };
+    let differ = |is_acl: bool| {
+        let select = |attrs: &Vec<(std::ffi::OsString, Vec<u8>)>| {
+            attrs
+                .iter()
+                .filter(|(name, _)| {
+                    ACL_NAMES.iter().any(|acl| name == acl) == is_acl
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        select(&left_attrs) != select(&right_attrs)
+    };
+    (differ(false), differ(true))
+}
+
+/// Compares the extended attributes of the items at `left` and `right`.
+#[cfg(not(unix))]
+fn cmp_xattrs(_left: &Path, _right: &Path) -> (bool, bool) {
+    (false, false)
+}
+
+/// Returns the extended attributes of the item at `path`, sorted by name,
+/// or `None` if they can't be read.
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> Option<Vec<(std::ffi::OsString, Vec<u8>)>> {
+    let mut attrs = Vec::new();
+    for name in xattr::list(path).ok()? {
+        let value = xattr::get(path, &name).ok()?.unwrap_or_default();
+        attrs.push((name, value));
+    }
+    attrs.sort();
+    Some(attrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cmp_attributes() -> Result<(), Box<dyn std::error::Error>> {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let tmp = tempdir()?;
+        let left = tmp.path().join("left.sh");
+        let right = tmp.path().join("right.sh");
+        fs::write(&left, "echo")?;
+        fs::write(&right, "echo")?;
+        fs::set_permissions(&left, fs::Permissions::from_mode(0o644))?;
+        fs::set_permissions(&right, fs::Permissions::from_mode(0o644))?;
+        let diff = |left, right| async move {
+            cmp_attributes(&FSItem::new(left).await, &FSItem::new(right).await)
+                .await
+        };
+        assert!(diff(&left, &right).await?.is_empty());
+        fs::set_permissions(&right, fs::Permissions::from_mode(0o755))?;
+        assert_eq!(
+            diff(&left, &right).await?,
+            AttrDiff {
+                mode: true,
+                ..Default::default()
+            }
+        );
+        // Not all file systems support user attributes
+        if xattr::set(&left, "user.cocomo", b"1").is_ok() {
+            let attr_diff = diff(&left, &right).await?;
+            assert!(attr_diff.xattrs);
+            assert!(!attr_diff.acl);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Cancellation Module (`cancel`)
+//!
+//! This module provides a token for cancelling long-running operations like
+//! comparing directories or copying, moving and deleting items.
+
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// A token for cancelling long-running operations.
+///
+/// Clones of a token share their state, so an operation can be cancelled
+/// from another task by calling [`CancelToken::cancel`] on a clone of the
+/// token handed to the operation. The operation stops at the next point
+/// where it can do so without leaving a partially written file behind.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Creates a new token, not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests the cancellation of the operations using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns an error of kind `Interrupted` if cancellation has been
+    /// requested.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "operation cancelled",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert_eq!(
+            clone.check().unwrap_err().kind(),
+            io::ErrorKind::Interrupted
+        );
+    }
+}
@@ -0,0 +1,133 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # File Content Module (`content`)
+//!
+//! This internal module provides helpers for comparing the content of files.
+
+use std::path::Path;
+
+use tokio::{
+    fs::File,
+    io::{self, AsyncReadExt, BufReader},
+};
+use xxhash_rust::xxh3::Xxh3;
+
+/// Size of the buffers used when reading file content.
+const BUF_SIZE: usize = 64 * 1024;
+
+/// Reads from `reader` until `buf` is full or EOF is reached and returns the
+/// number of bytes read.
+async fn read_full<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..]).await?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+/// Returns `true` if the files at `left` and `right` have identical content.
+///
+/// The files are compared byte by byte, stopping at the first difference.
+/// `on_read` is called with the number of bytes read after each chunk; an
+/// error returned from it stops the comparison.
+pub(crate) async fn same_content(
+    left: &Path,
+    right: &Path,
+    mut on_read: impl FnMut(u64) -> io::Result<()>,
+) -> io::Result<bool> {
+    let mut left_reader = BufReader::new(File::open(left).await?);
+    let mut right_reader = BufReader::new(File::open(right).await?);
+    let mut left_buf = vec![0_u8; BUF_SIZE];
+    let mut right_buf = vec![0_u8; BUF_SIZE];
+    loop {
+        let left_len = read_full(&mut left_reader, &mut left_buf).await?;
+        let right_len = read_full(&mut right_reader, &mut right_buf).await?;
+        on_read((left_len + right_len) as u64)?;
+        if left_len != right_len
+            || left_buf[..left_len] != right_buf[..right_len]
+        {
+            return Ok(false);
+        }
+        if left_len == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Returns the 128-bit XXH3 hash of the content of the file at `path`.
+///
+/// `on_read` is called with the number of bytes read after each chunk; an
+/// error returned from it stops reading.
+pub(crate) async fn content_hash(
+    path: &Path,
+    mut on_read: impl FnMut(u64) -> io::Result<()>,
+) -> io::Result<u128> {
+    let mut reader = File::open(path).await?;
+    let mut buf = vec![0_u8; BUF_SIZE];
+    let mut hasher = Xxh3::new();
+    loop {
+        let n = reader.read(&mut buf).await?;
+        on_read(n as u64)?;
+        if n == 0 {
+            return Ok(hasher.digest128());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use tokio::fs;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_same_content() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let file1 = tmp_dir.join("file1");
+        let file2 = tmp_dir.join("file2");
+        let file3 = tmp_dir.join("file3");
+        let file4 = tmp_dir.join("file4");
+        let big: Vec<u8> =
+            (0..3 * BUF_SIZE).map(|i| (i % 251) as u8).collect();
+        fs::write(&file1, &big).await?;
+        fs::write(&file2, &big).await?;
+        let mut modified = big.clone();
+        modified[2 * BUF_SIZE + 7] ^= 0xff;
+        fs::write(&file3, &modified).await?;
+        fs::write(&file4, &big[..BUF_SIZE]).await?;
+        let mut bytes_read = 0;
+        assert!(
+            same_content(&file1, &file2, |n| {
+                bytes_read += n;
+                Ok(())
+            })
+            .await?
+        );
+        assert_eq!(bytes_read, 2 * big.len() as u64);
+        assert!(!same_content(&file1, &file3, |_| Ok(())).await?);
+        assert!(!same_content(&file1, &file4, |_| Ok(())).await?);
+        assert!(!same_content(&file4, &file1, |_| Ok(())).await?);
+        let hash = content_hash(&file1, |_| Ok(())).await?;
+        assert_eq!(content_hash(&file2, |_| Ok(())).await?, hash);
+        assert_ne!(content_hash(&file3, |_| Ok(())).await?, hash);
+        assert_ne!(content_hash(&file4, |_| Ok(())).await?, hash);
+        Ok(())
+    }
+}
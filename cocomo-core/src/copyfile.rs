@@ -0,0 +1,304 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # File Copy Module (`copyfile`)
+//!
+//! This internal module copies the content of a file using the fastest
+//! strategy available: cloning the file by reflink on copy-on-write file
+//! systems, copying it inside the kernel via `copy_file_range`, or copying
+//! it through a user space buffer. Holes in sparse files are preserved
+//! unless the file gets cloned, which shares them anyway.
+//!
+//! The functions in this module block and have to be run on a thread
+//! dedicated to blocking operations.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    cancel::CancelToken,
+    fsops::{COPY_BUF_SIZE, CopyStrategy},
+};
+
+/// Maximum number of bytes copied by a single call of `copy_file_range`, so
+/// that cancellation is noticed in time.
+#[cfg(target_os = "linux")]
+const RANGE_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Copies the content of the file `src` to the existing, empty file `dst`
+/// and returns the strategy used.
+///
+/// Cancellation via `cancel` is reported as an error of kind `Interrupted`.
+pub(crate) fn copy_content(
+    src: &Path,
+    dst: &Path,
+    cancel: &CancelToken,
+) -> io::Result<CopyStrategy> {
+    let mut reader = File::open(src)?;
+    let mut writer = OpenOptions::new().write(true).open(dst)?;
+    #[cfg(target_os = "linux")]
+    if linux::reflink(&reader, &writer).is_ok() {
+        return Ok(CopyStrategy::Reflink);
+    }
+    let len = reader.metadata()?.len();
+    let segments = data_segments(&reader, len)?;
+    #[cfg(target_os = "linux")]
+    if linux::copy_ranges(&reader, &writer, &segments, cancel)? {
+        writer.set_len(len)?;
+        return Ok(CopyStrategy::CopyFileRange);
+    }
+    let mut buf = vec![0_u8; COPY_BUF_SIZE];
+    for &(start, end) in &segments {
+        reader.seek(SeekFrom::Start(start))?;
+        writer.seek(SeekFrom::Start(start))?;
+        let mut remaining = end - start;
+        while remaining > 0 {
+            cancel.check()?;
+            let max = usize::try_from(remaining)
+                .map_or(COPY_BUF_SIZE, |n| n.min(COPY_BUF_SIZE));
+            let n = reader.read(&mut buf[..max])?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buf[..n])?;
+            remaining -= n as u64;
+        }
+    }
+    // Creates the trailing hole, if any
+    writer.set_len(len)?;
+    Ok(CopyStrategy::Plain)
+}
+
+/// Returns the ranges of `file` of length `len` holding data, skipping the
+/// holes of a sparse file.
+#[cfg(target_os = "linux")]
+fn data_segments(file: &File, len: u64) -> io::Result<Vec<(u64, u64)>> {
+    use std::os::unix::fs::MetadataExt;
+
+    if file.metadata()?.blocks() * 512 >= len {
+        // Not sparse
+        return Ok(vec![(0, len)]);
+    }
+    Ok(linux::data_segments(file, len).unwrap_or_else(|_| vec![(0, len)]))
+}
+
+/// Returns the ranges of `file` of length `len` holding data.
+#[cfg(not(target_os = "linux"))]
+fn data_segments(_file: &File, len: u64) -> io::Result<Vec<(u64, u64)>> {
+    Ok(vec![(0, len)])
+}
+
+/// Drops the pages of `file` from the page cache, so that the file gets
+/// read from the storage device afterwards. Only pages already written to
+/// the device are dropped, so `file` should have been synced before.
+#[cfg(target_os = "linux")]
+pub(crate) fn evict_cached(file: &File) -> io::Result<()> {
+    linux::evict_cached(file)
+}
+
+/// Drops the pages of `file` from the page cache; not supported on this
+/// platform, so the file may still be read from the cache afterwards.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn evict_cached(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{fs::File, io, os::fd::AsRawFd};
+
+    use super::RANGE_CHUNK_SIZE;
+    use crate::cancel::CancelToken;
+
+    /// Makes `writer` share the data of `reader`.
+    pub(super) fn reflink(reader: &File, writer: &File) -> io::Result<()> {
+        // SAFETY: both file descriptors are valid while the files are
+        // borrowed.
+        let res = unsafe {
+            libc::ioctl(writer.as_raw_fd(), libc::FICLONE, reader.as_raw_fd())
+        };
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Advises the kernel to drop the cached pages of `file`.
+    pub(super) fn evict_cached(file: &File) -> io::Result<()> {
+        // SAFETY: the file descriptor is valid while the file is borrowed.
+        let res = unsafe {
+            libc::posix_fadvise(
+                file.as_raw_fd(),
+                0,
+                0,
+                libc::POSIX_FADV_DONTNEED,
+            )
+        };
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(res))
+        }
+    }
+
+    /// Returns the ranges of `file` of length `len` holding data.
+    pub(super) fn data_segments(
+        file: &File,
+        len: u64,
+    ) -> io::Result<Vec<(u64, u64)>> {
+        let seek = |offset: u64, whence: libc::c_int| -> io::Result<u64> {
+            let offset = libc::off_t::try_from(offset)
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+            // SAFETY: the file descriptor is valid while the file is
+            // borrowed.
+            let pos = unsafe { libc::lseek(file.as_raw_fd(), offset, whence) };
+            u64::try_from(pos).map_err(|_| io::Error::last_os_error())
+        };
+        let mut segments = Vec::new();
+        let mut pos = 0;
+        while pos < len {
+            let start = match seek(pos, libc::SEEK_DATA) {
+                Ok(start) => start,
+                // No more data
+                Err(err) if err.raw_os_error() == Some(libc::ENXIO) => break,
+                Err(err) => return Err(err),
+            };
+            let end = seek(start, libc::SEEK_HOLE)?.min(len);
+            segments.push((start, end));
+            pos = end;
+        }
+        Ok(segments)
+    }
+
+    /// Copies the given `segments` of `reader` to the same positions in
+    /// `writer` via `copy_file_range`.
+    ///
+    /// Returns `false` if `copy_file_range` is not supported for the files,
+    /// without having copied anything.
+    pub(super) fn copy_ranges(
+        reader: &File,
+        writer: &File,
+        segments: &[(u64, u64)],
+        cancel: &CancelToken,
+    ) -> io::Result<bool> {
+        let mut copied_any = false;
+        for &(start, end) in segments {
+            let mut pos = start;
+            while pos < end {
+                cancel.check()?;
+                let mut off_in =
+                    libc::loff_t::try_from(pos).map_err(|_| {
+                        io::Error::from(io::ErrorKind::InvalidInput)
+                    })?;
+                let mut off_out = off_in;
+                let chunk = usize::try_from((end - pos).min(RANGE_CHUNK_SIZE))
+                    .unwrap_or(usize::MAX);
+                // SAFETY: both file descriptors are valid while the files
+                // are borrowed, and the offsets point to local variables.
+                let n = unsafe {
+                    libc::copy_file_range(
+                        reader.as_raw_fd(),
+                        &raw mut off_in,
+                        writer.as_raw_fd(),
+                        &raw mut off_out,
+                        chunk,
+                        0,
+                    )
+                };
+                match u64::try_from(n) {
+                    // The file has been truncated meanwhile
+                    Ok(0) => return Ok(true),
+                    Ok(n) => pos += n,
+                    Err(_) => {
+                        let err = io::Error::last_os_error();
+                        let unsupported = matches!(
+                            err.raw_os_error(),
+                            Some(
+                                libc::ENOSYS
+                                    | libc::EXDEV
+                                    | libc::EOPNOTSUPP
+                                    | libc::EINVAL
+                            )
+                        );
+                        if unsupported && !copied_any {
+                            return Ok(false);
+                        }
+                        return Err(err);
+                    }
+                }
+                copied_any = true;
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_copy_content() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src = tmp_dir.join("src.bin");
+        let dst = tmp_dir.join("dst.bin");
+        let content: Vec<u8> = (0..3 * COPY_BUF_SIZE + 5)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(&src, &content)?;
+        File::create(&dst)?;
+        let cancel = CancelToken::new();
+        copy_content(&src, &dst, &cancel)?;
+        assert_eq!(std::fs::read(&dst)?, content);
+        // Holes are preserved
+        let sparse = tmp_dir.join("sparse.bin");
+        let mut file = File::create(&sparse)?;
+        file.write_all(b"head")?;
+        file.seek(SeekFrom::Start(64 * 1024 * 1024))?;
+        file.write_all(b"tail")?;
+        file.set_len(128 * 1024 * 1024)?;
+        drop(file);
+        std::fs::remove_file(&dst)?;
+        File::create(&dst)?;
+        copy_content(&sparse, &dst, &cancel)?;
+        let copy = std::fs::read(&dst)?;
+        assert_eq!(copy.len(), 128 * 1024 * 1024);
+        assert_eq!(&copy[..4], b"head");
+        assert_eq!(&copy[64 * 1024 * 1024..64 * 1024 * 1024 + 4], b"tail");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let src_blocks = std::fs::metadata(&sparse)?.blocks();
+            if src_blocks * 512 < 128 * 1024 * 1024 {
+                // The file system supports sparse files
+                assert!(
+                    std::fs::metadata(&dst)?.blocks() * 512
+                        < 128 * 1024 * 1024
+                );
+            }
+        }
+        // Cancellation is reported
+        cancel.cancel();
+        std::fs::remove_file(&dst)?;
+        File::create(&dst)?;
+        match copy_content(&src, &dst, &cancel) {
+            // Cloning is instantaneous and not interrupted
+            Ok(strategy) => assert_eq!(strategy, CopyStrategy::Reflink),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::Interrupted),
+        }
+        Ok(())
+    }
+}
@@ -12,9 +12,24 @@
 //! This module provides the logic for comparing two directories. It computes
 //! a list of differences between the files and subdirectories found in each.
 
-use std::{cmp, ffi, io};
+use std::{
+    cmp, ffi, fs, io,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use crate::{fsitem::FSItem, readdir::read_dir};
+use tokio::task;
+
+use crate::{
+    attrs::{AttrDiff, cmp_attributes},
+    cancel::CancelToken,
+    content::same_content,
+    fsitem::FSItem,
+    readdir::{ItemFilter, read_dir},
+    renames::detect_renames,
+    scan::{Scan, ScanEvent, ScanEventSender},
+};
 
 const EMPTY: &ffi::OsString = &ffi::OsString::new();
 
@@ -34,6 +49,24 @@ pub enum By {
     Metadata,
     /// Full content match.
     Content,
+    /// Both sides are distinct hard links to the same regular file, so
+    /// their content is identical without being read.
+    Inode,
+    /// Both sides are symbolic links with the same target path.
+    LinkTarget,
+}
+
+/// Modes for comparing symbolic links existing on both sides.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum LinkMode {
+    /// Compare the links themselves like files, by their metadata.
+    #[default]
+    Opaque,
+    /// Compare the target paths of the links as strings.
+    Target,
+    /// Compare the items the links resolve to, by metadata and, if
+    /// requested, by content. Broken links are compared by target path.
+    Resolve,
 }
 
 /// The type of difference found between two items with the same name.
@@ -53,6 +86,69 @@ pub enum DiffItemType {
         /// How the equality was determined.
         by: By,
     },
+    /// A file found only on the left side and a file found only on the
+    /// right side, at a different path, are regarded as the same file
+    /// renamed or moved.
+    Renamed {
+        /// `true` if the content of both files is identical, `false` if it
+        /// is only similar.
+        identical: bool,
+    },
+}
+
+/// Options controlling how the items of two directories are compared.
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    /// Compare the content of files existing on both sides in addition to
+    /// their metadata. Files with identical content are reported as
+    /// `Same { by: By::Content }`, all others as `Different`.
+    pub by_content: bool,
+    /// Compare subdirectories recursively. Each directory item then carries
+    /// the comparison of its subtree and its type reflects the aggregated
+    /// status of that subtree.
+    pub recursive: bool,
+    /// How to compare symbolic links existing on both sides.
+    pub links: LinkMode,
+    /// Filter for excluding entries from the comparison.
+    pub filter: ItemFilter,
+    /// Maximum difference between modification times still regarded as
+    /// equal (e.g. 2 seconds for FAT file systems).
+    pub mtime_tolerance: Duration,
+    /// Maximum number of whole hours by which modification times may differ
+    /// and still be regarded as equal (within `mtime_tolerance`). Use 1 to
+    /// compensate DST shifts, more to compensate time zone offsets.
+    pub max_hour_offset: u32,
+    /// Ignore modification times: files are compared by size and, if the
+    /// sizes are equal, by content. No side is ever reported as newer.
+    pub ignore_mtime: bool,
+    /// Pair files found only on the left side with files of identical
+    /// content found only on the right side and report them as
+    /// `Renamed`. With `recursive` set, files moved to another directory
+    /// are paired as well.
+    pub detect_renames: bool,
+    /// Compare permission bits, owner, group, extended attributes and ACLs
+    /// of items existing on both sides. The differences found are given by
+    /// [`DiffItem::attr_diff`]; they don't change the type of an item.
+    pub compare_attributes: bool,
+    /// Minimum similarity (between 0 and 1) of the lines of text files with
+    /// different content for being paired as renamed; 0 disables comparing
+    /// by similarity. Only used with `detect_renames` set.
+    pub rename_similarity: f32,
+    /// Maximum number of I/O bound tasks (reading entries, comparing
+    /// content) run concurrently; 0 means: number of available CPUs.
+    pub parallelism: usize,
+    /// Channel receiving progress events while comparing.
+    pub progress: Option<ScanEventSender>,
+    /// Token for cancelling the comparison. A cancelled comparison fails
+    /// with an error of kind `Interrupted`.
+    pub cancel: CancelToken,
+}
+
+impl DiffOptions {
+    /// Creates the state shared by all tasks of a comparison.
+    fn new_scan(&self) -> Scan {
+        Scan::new(self.parallelism, self.progress.clone(), self.cancel.clone())
+    }
 }
 
 /// A single entry in a directory comparison result.
@@ -64,6 +160,12 @@ pub struct DiffItem {
     pub left_item: Option<FSItem>,
     /// The file system item from the right side, if it exists.
     pub right_item: Option<FSItem>,
+    /// The comparison of the directory's content, if the item is a directory
+    /// and the comparison is recursive.
+    pub sub_diff: Option<Box<DirDiff>>,
+    /// The attributes differing between both sides, if the item exists on
+    /// both sides and attributes are compared.
+    pub attr_diff: Option<AttrDiff>,
 }
 
 impl DiffItem {
@@ -71,52 +173,50 @@ impl DiffItem {
     pub fn new(
         left_item: &Option<FSItem>,
         right_item: &Option<FSItem>,
+    ) -> io::Result<Self> {
+        Self::by_metadata(left_item, right_item, &DiffOptions::default())
+    }
+
+    /// Compares two optional [`FSItem`]s by their metadata, taking into
+    /// account the timestamp related options.
+    fn by_metadata(
+        left_item: &Option<FSItem>,
+        right_item: &Option<FSItem>,
+        options: &DiffOptions,
     ) -> io::Result<Self> {
         match (left_item, right_item) {
             (Some(left), Some(right)) => Ok(Self {
                 diff_item_type: match (left.metadata(), right.metadata()) {
-                    (None, _) | (_, None) => {
-                        DiffItemType::Different { newer: None }
+                    _ if left.is_file()
+                        && right.is_file()
+                        && left.path() != right.path()
+                        && left.is_same_file(right) =>
+                    {
+                        DiffItemType::Same { by: By::Inode }
                     }
                     (Some(left_meta), Some(right_meta)) => {
-                        match (left_meta.modified(), right_meta.modified()) {
-                            (Ok(left_time), Ok(right_time)) => match left_time
-                                .cmp(&right_time)
-                            {
-                                cmp::Ordering::Less => {
-                                    DiffItemType::Different {
-                                        newer: Some(DiffSide::Right),
-                                    }
-                                }
-                                cmp::Ordering::Greater => {
-                                    DiffItemType::Different {
-                                        newer: Some(DiffSide::Left),
-                                    }
-                                }
-                                _ => {
-                                    if left_meta.len() == right_meta.len() {
-                                        DiffItemType::Same { by: By::Metadata }
-                                    } else {
-                                        DiffItemType::Different { newer: None }
-                                    }
-                                }
-                            },
-                            _ => DiffItemType::Different { newer: None },
-                        }
+                        cmp_metadata(left_meta, right_meta, options)
                     }
+                    _ => DiffItemType::Different { newer: None },
                 },
                 left_item: left_item.clone(),
                 right_item: right_item.clone(),
+                sub_diff: None,
+                attr_diff: None,
             }),
             (Some(..), None) => Ok(Self {
                 diff_item_type: DiffItemType::LeftOnly,
                 left_item: left_item.clone(),
                 right_item: right_item.clone(),
+                sub_diff: None,
+                attr_diff: None,
             }),
             (None, Some(..)) => Ok(Self {
                 diff_item_type: DiffItemType::RightOnly,
                 left_item: left_item.clone(),
                 right_item: right_item.clone(),
+                sub_diff: None,
+                attr_diff: None,
             }),
             _ => Err(io::Error::other(
                 "Internal error: both sides of diff item empty.",
@@ -124,6 +224,93 @@ impl DiffItem {
         }
     }
 
+    /// Compares two optional [`FSItem`]s according to the given options and
+    /// returns a `DiffItem`.
+    ///
+    /// Without any options set, this gives the same result as
+    /// [`DiffItem::new`].
+    pub async fn compare(
+        left_item: &Option<FSItem>,
+        right_item: &Option<FSItem>,
+        options: &DiffOptions,
+    ) -> io::Result<Self> {
+        let scan = options.new_scan();
+        Self::compare_in(
+            left_item.clone(),
+            right_item.clone(),
+            Arc::new(options.clone()),
+            scan,
+        )
+        .await
+    }
+
+    /// Compares two optional [`FSItem`]s according to the given options as
+    /// part of the comparison `scan`.
+    async fn compare_in(
+        left_item: Option<FSItem>,
+        right_item: Option<FSItem>,
+        options: Arc<DiffOptions>,
+        scan: Scan,
+    ) -> io::Result<Self> {
+        scan.check()?;
+        let mut diff_item =
+            Self::by_metadata(&left_item, &right_item, &options)?;
+        if (options.by_content || options.ignore_mtime)
+            && let (Some(left), Some(right)) = (&left_item, &right_item)
+            && left.is_file()
+            && right.is_file()
+            && !matches!(
+                diff_item.diff_item_type,
+                DiffItemType::Same { by: By::Inode }
+            )
+        {
+            let _permit = scan.permit().await;
+            diff_item.diff_item_type =
+                cmp_content(left, right, diff_item.diff_item_type, &scan)
+                    .await?;
+        }
+        if let (Some(left), Some(right)) = (&left_item, &right_item)
+            && left.is_link()
+            && right.is_link()
+        {
+            diff_item.diff_item_type = cmp_links(
+                left,
+                right,
+                diff_item.diff_item_type,
+                &options,
+                &scan,
+            )
+            .await?;
+        }
+        if options.compare_attributes
+            && let (Some(left), Some(right)) = (&left_item, &right_item)
+        {
+            let _permit = scan.permit().await;
+            diff_item.attr_diff = Some(cmp_attributes(left, right).await?);
+        }
+        if options.recursive && diff_item.is_dir() {
+            let left_dir = left_item.filter(FSItem::is_dir);
+            let right_dir = right_item.filter(FSItem::is_dir);
+            let both_sides = left_dir.is_some() && right_dir.is_some();
+            let sub_diff = DirDiff::scan(
+                left_dir,
+                right_dir,
+                DiffOptions::clone(&options),
+                scan,
+            )
+            .await?;
+            if both_sides {
+                diff_item.diff_item_type = sub_diff.status();
+                if let Some(attr_diff) = &mut diff_item.attr_diff {
+                    attr_diff.entries =
+                        sub_diff.items.iter().any(DiffItem::attributes_differ);
+                }
+            }
+            diff_item.sub_diff = Some(Box::new(sub_diff));
+        }
+        Ok(diff_item)
+    }
+
     /// Returns the name of the item.
     pub fn name(&self) -> &ffi::OsString {
         if let Some(left_item) = &self.left_item {
@@ -136,6 +323,20 @@ impl DiffItem {
         EMPTY
     }
 
+    /// Returns `true` if the item is a directory on at least one side.
+    pub fn is_dir(&self) -> bool {
+        self.left_item.as_ref().is_some_and(FSItem::is_dir)
+            || self.right_item.as_ref().is_some_and(FSItem::is_dir)
+    }
+
+    /// Returns `true` if the item exists on both sides and attributes, of
+    /// the item itself or of entries inside of it, have been found to
+    /// differ.
+    pub fn attributes_differ(&self) -> bool {
+        self.attr_diff
+            .is_some_and(|attr_diff| !attr_diff.is_empty())
+    }
+
     /// Returns `true` if the item exists on both sides and the left one is
     /// newer.
     pub fn left_newer(&self) -> bool {
@@ -159,6 +360,159 @@ impl DiffItem {
     }
 }
 
+/// Compares two modification times, regarding them as equal if they differ
+/// by at most `options.mtime_tolerance` or by a whole number of hours not
+/// exceeding `options.max_hour_offset` (within the same tolerance).
+fn cmp_mtime(
+    left_time: SystemTime,
+    right_time: SystemTime,
+    options: &DiffOptions,
+) -> cmp::Ordering {
+    let (delta, ordering) = match right_time.duration_since(left_time) {
+        Ok(delta) => (delta, cmp::Ordering::Less),
+        Err(err) => (err.duration(), cmp::Ordering::Greater),
+    };
+    // Only the whole number of hours nearest to the difference can be
+    // within the tolerance
+    let hours = (delta.as_secs().saturating_add(1800) / 3600)
+        .min(u64::from(options.max_hour_offset));
+    let offset = Duration::from_secs(hours * 3600);
+    if delta.abs_diff(offset) <= options.mtime_tolerance {
+        cmp::Ordering::Equal
+    } else {
+        ordering
+    }
+}
+
+/// Compares two items by their metadata.
+fn cmp_metadata(
+    left_meta: &fs::Metadata,
+    right_meta: &fs::Metadata,
+    options: &DiffOptions,
+) -> DiffItemType {
+    let same_len = left_meta.len() == right_meta.len();
+    if options.ignore_mtime {
+        return if same_len {
+            DiffItemType::Same { by: By::Metadata }
+        } else {
+            DiffItemType::Different { newer: None }
+        };
+    }
+    match (left_meta.modified(), right_meta.modified()) {
+        (Ok(left_time), Ok(right_time)) => {
+            match cmp_mtime(left_time, right_time, options) {
+                cmp::Ordering::Less => DiffItemType::Different {
+                    newer: Some(DiffSide::Right),
+                },
+                cmp::Ordering::Greater => DiffItemType::Different {
+                    newer: Some(DiffSide::Left),
+                },
+                cmp::Ordering::Equal if same_len => {
+                    DiffItemType::Same { by: By::Metadata }
+                }
+                cmp::Ordering::Equal => {
+                    DiffItemType::Different { newer: None }
+                }
+            }
+        }
+        _ => DiffItemType::Different { newer: None },
+    }
+}
+
+/// Refines the metadata based result `by_metadata` of comparing the files
+/// `left` and `right` by comparing their content.
+async fn cmp_content(
+    left: &FSItem,
+    right: &FSItem,
+    by_metadata: DiffItemType,
+    scan: &Scan,
+) -> io::Result<DiffItemType> {
+    let newer = match by_metadata {
+        DiffItemType::Different { newer } => newer,
+        _ => None,
+    };
+    let same_len = match (left.metadata(), right.metadata()) {
+        (Some(left_meta), Some(right_meta)) => {
+            left_meta.len() == right_meta.len()
+        }
+        _ => false,
+    };
+    if same_len
+        && same_content(left.path(), right.path(), |n| {
+            scan.report(ScanEvent::Read(n));
+            scan.check()
+        })
+        .await?
+    {
+        Ok(DiffItemType::Same { by: By::Content })
+    } else {
+        Ok(DiffItemType::Different { newer })
+    }
+}
+
+/// Refines the metadata based result `by_metadata` of comparing the
+/// symbolic links `left` and `right` according to `options.links`.
+async fn cmp_links(
+    left: &FSItem,
+    right: &FSItem,
+    by_metadata: DiffItemType,
+    options: &DiffOptions,
+    scan: &Scan,
+) -> io::Result<DiffItemType> {
+    match options.links {
+        LinkMode::Opaque => Ok(by_metadata),
+        LinkMode::Target => Ok(cmp_link_targets(left, right, by_metadata)),
+        LinkMode::Resolve => {
+            let left_target = left.unlink().await;
+            let right_target = right.unlink().await;
+            match (left_target.metadata(), right_target.metadata()) {
+                (Some(left_meta), Some(right_meta))
+                    if !left_target.is_link() && !right_target.is_link() =>
+                {
+                    let by_metadata =
+                        cmp_metadata(left_meta, right_meta, options);
+                    if (options.by_content || options.ignore_mtime)
+                        && left_target.is_file()
+                        && right_target.is_file()
+                    {
+                        let _permit = scan.permit().await;
+                        cmp_content(
+                            &left_target,
+                            &right_target,
+                            by_metadata,
+                            scan,
+                        )
+                        .await
+                    } else {
+                        Ok(by_metadata)
+                    }
+                }
+                // Broken links or loops
+                _ => Ok(cmp_link_targets(left, right, by_metadata)),
+            }
+        }
+    }
+}
+
+/// Compares the target paths of the symbolic links `left` and `right`,
+/// taking the newer side from the metadata based result `by_metadata`.
+fn cmp_link_targets(
+    left: &FSItem,
+    right: &FSItem,
+    by_metadata: DiffItemType,
+) -> DiffItemType {
+    if left.link_target() == right.link_target() {
+        DiffItemType::Same { by: By::LinkTarget }
+    } else {
+        match by_metadata {
+            DiffItemType::Different { newer } => {
+                DiffItemType::Different { newer }
+            }
+            _ => DiffItemType::Different { newer: None },
+        }
+    }
+}
+
 /// A complete result of a comparison between two directories.
 #[derive(Clone, Debug)]
 pub struct DirDiff {
@@ -168,6 +522,8 @@ pub struct DirDiff {
     pub right_dir: Option<FSItem>,
     /// The list of compared entries within these directories.
     pub items: Vec<DiffItem>,
+    /// The options used for the comparison.
+    pub options: DiffOptions,
 }
 
 #[inline]
@@ -180,45 +536,48 @@ type DiffItemList = Vec<DiffItem>;
 async fn make_diff(
     left_dir: &Option<FSItem>,
     right_dir: &Option<FSItem>,
+    options: &DiffOptions,
+    scan: &Scan,
 ) -> io::Result<DiffItemList> {
     let mut left_items = if let Some(dir) = left_dir {
-        read_dir(dir).await?
+        read_dir(dir, &options.filter, scan).await?
     } else {
         Vec::new()
     };
     let mut right_items = if let Some(dir) = right_dir {
-        read_dir(dir).await?
+        read_dir(dir, &options.filter, scan).await?
     } else {
         Vec::new()
     };
     left_items.sort_by(|a, b| cmp_items(b, a));
     right_items.sort_by(|a, b| cmp_items(b, a));
-    let mut diff_items: DiffItemList = Vec::new();
+    // Align the items of both sides by name
+    let mut pairs = Vec::new();
     let mut left_item = left_items.pop();
     let mut right_item = right_items.pop();
     loop {
         match (&left_item, &right_item) {
             (Some(left), Some(right)) => match cmp_items(left, right) {
                 cmp::Ordering::Equal => {
-                    diff_items.push(DiffItem::new(&left_item, &right_item)?);
+                    pairs.push((left_item, right_item));
                     left_item = left_items.pop();
                     right_item = right_items.pop();
                 }
                 cmp::Ordering::Less => {
-                    diff_items.push(DiffItem::new(&left_item, &None)?);
+                    pairs.push((left_item, None));
                     left_item = left_items.pop();
                 }
                 cmp::Ordering::Greater => {
-                    diff_items.push(DiffItem::new(&None, &right_item)?);
+                    pairs.push((None, right_item));
                     right_item = right_items.pop();
                 }
             },
             (Some(..), None) => {
-                diff_items.push(DiffItem::new(&left_item, &right_item)?);
+                pairs.push((left_item, None));
                 left_item = left_items.pop();
             }
             (None, Some(..)) => {
-                diff_items.push(DiffItem::new(&left_item, &right_item)?);
+                pairs.push((None, right_item));
                 right_item = right_items.pop();
             }
             _ => {
@@ -226,6 +585,26 @@ async fn make_diff(
             }
         }
     }
+    // Compare the aligned items concurrently
+    let options = Arc::new(options.clone());
+    let tasks: Vec<_> = pairs
+        .into_iter()
+        .map(|(left, right)| {
+            task::spawn(DiffItem::compare_in(
+                left,
+                right,
+                options.clone(),
+                scan.clone(),
+            ))
+        })
+        .collect();
+    let mut diff_items: DiffItemList = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        diff_items.push(task.await??);
+    }
+    if let Some(dir) = left_dir.as_ref().or(right_dir.as_ref()) {
+        scan.report(ScanEvent::Compared(dir.path().clone()));
+    }
     Ok(diff_items)
 }
 
@@ -235,12 +614,53 @@ impl DirDiff {
         left_dir: &Option<FSItem>,
         right_dir: &Option<FSItem>,
     ) -> io::Result<Self> {
-        debug_assert!(left_dir.is_some() || right_dir.is_some());
-        let diff_items = make_diff(left_dir, right_dir).await?;
-        Ok(Self {
-            left_dir: left_dir.to_owned(),
-            right_dir: right_dir.to_owned(),
-            items: diff_items,
+        Self::with_options(left_dir, right_dir, DiffOptions::default()).await
+    }
+
+    /// Compares the contents of two directories according to the given
+    /// options.
+    pub async fn with_options(
+        left_dir: &Option<FSItem>,
+        right_dir: &Option<FSItem>,
+        mut options: DiffOptions,
+    ) -> io::Result<Self> {
+        if !options.filter.has_roots() {
+            let roots = left_dir.iter().chain(right_dir);
+            options.filter = options
+                .filter
+                .with_roots(roots.map(|dir| dir.path().clone()));
+        }
+        let scan = options.new_scan();
+        let mut diff = Self::scan(
+            left_dir.to_owned(),
+            right_dir.to_owned(),
+            options,
+            scan.clone(),
+        )
+        .await?;
+        diff.detect_renames(&scan).await?;
+        Ok(diff)
+    }
+
+    /// Compares the contents of two directories according to the given
+    /// options as part of the comparison `scan`.
+    fn scan(
+        left_dir: Option<FSItem>,
+        right_dir: Option<FSItem>,
+        options: DiffOptions,
+        scan: Scan,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Self>> + Send>> {
+        // Boxed because directories are compared recursively.
+        Box::pin(async move {
+            debug_assert!(left_dir.is_some() || right_dir.is_some());
+            let diff_items =
+                make_diff(&left_dir, &right_dir, &options, &scan).await?;
+            Ok(Self {
+                left_dir,
+                right_dir,
+                items: diff_items,
+                options,
+            })
         })
     }
 
@@ -258,8 +678,69 @@ impl DirDiff {
         }
     }
 
+    /// Returns the aggregated status of all compared entries.
+    ///
+    /// The result is `Same` if all entries are the same (`by: By::Content`
+    /// only if files are compared by content and all entries have been
+    /// compared by content or are hard links to the same file), otherwise
+    /// `Different`. If all entries differing by metadata are newer on the
+    /// same side, this side is reported as the newer one.
+    pub fn status(&self) -> DiffItemType {
+        let mut by = if self.options.by_content {
+            By::Content
+        } else {
+            By::Metadata
+        };
+        let mut newer = None;
+        let mut different = false;
+        for item in &self.items {
+            match item.diff_item_type {
+                DiffItemType::Same { by: By::Metadata } => by = By::Metadata,
+                DiffItemType::Same {
+                    by: By::Content | By::Inode | By::LinkTarget,
+                } => {}
+                DiffItemType::LeftOnly
+                | DiffItemType::RightOnly
+                | DiffItemType::Renamed { .. } => {
+                    return DiffItemType::Different { newer: None };
+                }
+                DiffItemType::Different { newer: None } => {
+                    return DiffItemType::Different { newer: None };
+                }
+                DiffItemType::Different { newer: Some(side) } => {
+                    if different && newer != Some(side) {
+                        return DiffItemType::Different { newer: None };
+                    }
+                    different = true;
+                    newer = Some(side);
+                }
+            }
+        }
+        if different {
+            DiffItemType::Different { newer }
+        } else {
+            DiffItemType::Same { by }
+        }
+    }
+
     pub async fn refresh(&mut self) -> io::Result<()> {
-        self.items = make_diff(&self.left_dir, &self.right_dir).await?;
+        let scan = self.options.new_scan();
+        self.items =
+            make_diff(&self.left_dir, &self.right_dir, &self.options, &scan)
+                .await?;
+        self.detect_renames(&scan).await
+    }
+
+    /// Pairs renamed and moved files, if requested by the options.
+    async fn detect_renames(&mut self, scan: &Scan) -> io::Result<()> {
+        if self.options.detect_renames {
+            detect_renames(
+                &mut self.items,
+                self.options.rename_similarity,
+                scan,
+            )
+            .await?;
+        }
         Ok(())
     }
 }
@@ -281,4 +762,79 @@ mod tests {
             .expect("Error creating diff");
         assert!(!diff.items.is_empty());
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hard_links() -> io::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let left = tmp.path().join("left");
+        let right = tmp.path().join("right");
+        fs::create_dir(&left)?;
+        fs::create_dir(&right)?;
+        fs::write(left.join("a.txt"), "aaa")?;
+        fs::hard_link(left.join("a.txt"), right.join("a.txt"))?;
+        let options = DiffOptions {
+            by_content: true,
+            ..Default::default()
+        };
+        let diff = DirDiff::with_options(
+            &Some(FSItem::new(&left).await),
+            &Some(FSItem::new(&right).await),
+            options,
+        )
+        .await?;
+        assert_eq!(
+            diff.items[0].diff_item_type,
+            DiffItemType::Same { by: By::Inode }
+        );
+        assert_eq!(diff.status(), DiffItemType::Same { by: By::Content });
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_self_compare() -> io::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        fs::create_dir(tmp.path().join("sub"))?;
+        fs::write(tmp.path().join("a.txt"), "aaa")?;
+        let dir = Some(FSItem::new(tmp.path()).await);
+        let diff =
+            DirDiff::with_options(&dir, &dir, Default::default()).await?;
+        for item in &diff.items {
+            assert_eq!(
+                item.diff_item_type,
+                DiffItemType::Same { by: By::Metadata }
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_empty_dirs_status() -> io::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let left = tmp.path().join("left");
+        let right = tmp.path().join("right");
+        fs::create_dir_all(left.join("sub"))?;
+        fs::create_dir_all(right.join("sub"))?;
+        for (by_content, by) in [(false, By::Metadata), (true, By::Content)] {
+            let options = DiffOptions {
+                by_content,
+                recursive: true,
+                ..Default::default()
+            };
+            let diff = DirDiff::with_options(
+                &Some(FSItem::new(&left).await),
+                &Some(FSItem::new(&right).await),
+                options,
+            )
+            .await?;
+            // Nothing has been compared by content unless asked for
+            assert_eq!(
+                diff.items[0].diff_item_type,
+                DiffItemType::Same { by }
+            );
+            assert_eq!(diff.status(), DiffItemType::Same { by });
+        }
+        Ok(())
+    }
 }
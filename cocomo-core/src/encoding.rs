@@ -0,0 +1,263 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Character Encoding Module (`encoding`)
+//!
+//! This module detects the character encoding of text files and decodes
+//! their content, so that files not encoded in UTF-8 can be compared as
+//! well.
+//!
+//! The encoding is detected by the byte order mark (BOM) at the start of a
+//! file, if any. Otherwise, content with many zero bytes at either odd or
+//! even positions is taken as UTF-16 without BOM, valid UTF-8 as UTF-8, and
+//! anything else is guessed heuristically among the legacy encodings, e.g.
+//! `windows-1252` (a superset of Latin-1) or `Shift_JIS`.
+
+use std::{fmt, io, iter};
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+/// Maximum number of bytes inspected for detecting UTF-16 without BOM.
+const UTF16_SAMPLE_SIZE: usize = 4096;
+
+/// The character encoding of a text file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextEncoding {
+    /// The encoding of the content.
+    pub encoding: &'static Encoding,
+    /// The content starts with a byte order mark.
+    pub bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.encoding.name())?;
+        if self.bom {
+            f.write_str(" BOM")?;
+        }
+        Ok(())
+    }
+}
+
+impl TextEncoding {
+    /// Detects the encoding of `bytes`, as described in the module
+    /// documentation.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return Self {
+                encoding,
+                bom: true,
+            };
+        }
+        let encoding = if let Some(encoding) = detect_utf16(bytes) {
+            encoding
+        } else if str::from_utf8(bytes).is_ok() {
+            UTF_8
+        } else {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(bytes, true);
+            detector.guess(None, true)
+        };
+        Self {
+            encoding,
+            bom: false,
+        }
+    }
+
+    /// Returns the encoding `encoding`, noting whether `bytes` start with
+    /// its byte order mark.
+    pub fn with_encoding(encoding: &'static Encoding, bytes: &[u8]) -> Self {
+        Self {
+            encoding,
+            bom: Encoding::for_bom(bytes)
+                .is_some_and(|(bom_encoding, _)| bom_encoding == encoding),
+        }
+    }
+
+    /// Decodes `bytes`, skipping the byte order mark, if any. Malformed
+    /// sequences are replaced by U+FFFD; the second value returned tells
+    /// whether there were any.
+    pub fn decode(&self, bytes: &[u8]) -> (String, bool) {
+        let bytes = match Encoding::for_bom(bytes) {
+            Some((_, bom_len)) if self.bom => &bytes[bom_len..],
+            _ => bytes,
+        };
+        let (text, malformed) =
+            self.encoding.decode_without_bom_handling(bytes);
+        (text.into_owned(), malformed)
+    }
+
+    /// Encodes `text`, prepending the byte order mark if there was one.
+    ///
+    /// Returns an error of kind `InvalidData` if `text` contains characters
+    /// which can't be represented in the encoding.
+    pub fn encode(&self, text: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            // `encoding_rs` only decodes UTF-16
+            let units = iter::once(0xfeff)
+                .filter(|_| self.bom)
+                .chain(text.encode_utf16());
+            for unit in units {
+                bytes.extend(if self.encoding == UTF_16LE {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                });
+            }
+            return Ok(bytes);
+        }
+        if self.bom {
+            bytes.extend_from_slice(b"\xef\xbb\xbf");
+        }
+        let (encoded, _, unmappable) = self.encoding.encode(text);
+        if unmappable {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "text contains characters not representable in {}",
+                    self.encoding.name()
+                ),
+            ));
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+}
+
+/// Returns `UTF-16LE` or `UTF-16BE` if `bytes` look like UTF-16 text without
+/// BOM, i.e. mostly ASCII characters with zero bytes at odd respectively
+/// even positions.
+fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(UTF16_SAMPLE_SIZE)];
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+    let (mut even_zeros, mut odd_zeros) = (0, 0);
+    for pair in sample.chunks_exact(2) {
+        even_zeros += usize::from(pair[0] == 0);
+        odd_zeros += usize::from(pair[1] == 0);
+    }
+    // Require a clear majority of zeros on one side and almost none on the
+    // other
+    if odd_zeros * 2 > pairs && even_zeros * 20 < pairs {
+        Some(UTF_16LE)
+    } else if even_zeros * 2 > pairs && odd_zeros * 20 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let detect = |bytes: &[u8]| {
+            let encoding = TextEncoding::detect(bytes);
+            (encoding, encoding.decode(bytes).0)
+        };
+        assert_eq!(
+            detect("Grüße\n".as_bytes()),
+            (TextEncoding::default(), "Grüße\n".to_string())
+        );
+        assert_eq!(
+            detect(b"\xef\xbb\xbfGr\xc3\xbc\xc3\x9fe\n"),
+            (
+                TextEncoding {
+                    encoding: UTF_8,
+                    bom: true
+                },
+                "Grüße\n".to_string()
+            )
+        );
+        assert_eq!(
+            detect(b"Die Stra\xdfe ist gr\xfcn und \xe4u\xdferst sch\xf6n\n"),
+            (
+                TextEncoding {
+                    encoding: WINDOWS_1252,
+                    bom: false
+                },
+                "Die Straße ist grün und äußerst schön\n".to_string()
+            )
+        );
+        let utf16: Vec<u8> = "Grüße\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(
+            detect(&utf16),
+            (
+                TextEncoding {
+                    encoding: UTF_16LE,
+                    bom: false
+                },
+                "Grüße\n".to_string()
+            )
+        );
+        let with_bom = [&[0xfe, 0xff][..], &[0, b'a', 0, b'\n']].concat();
+        assert_eq!(
+            detect(&with_bom),
+            (
+                TextEncoding {
+                    encoding: UTF_16BE,
+                    bom: true
+                },
+                "a\n".to_string()
+            )
+        );
+        // An explicit encoding overrides the detection
+        let encoding = TextEncoding::with_encoding(SHIFT_JIS, b"\x82\xa0");
+        assert!(!encoding.bom);
+        assert_eq!(encoding.decode(b"\x82\xa0").0, "あ");
+    }
+
+    #[test]
+    fn test_encode() {
+        for (encoding, bom) in [
+            (UTF_8, false),
+            (UTF_8, true),
+            (UTF_16LE, true),
+            (UTF_16BE, false),
+            (WINDOWS_1252, false),
+        ] {
+            let encoding = TextEncoding { encoding, bom };
+            let bytes = encoding.encode("Grüße\n").unwrap();
+            assert_eq!(TextEncoding::detect(&bytes), encoding);
+            assert_eq!(
+                encoding.decode(&bytes),
+                ("Grüße\n".to_string(), false)
+            );
+        }
+        let latin1 = TextEncoding {
+            encoding: WINDOWS_1252,
+            bom: false,
+        };
+        assert_eq!(
+            latin1.encode("→").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(latin1.decode(b"a\x81"), ("a\u{81}".to_string(), false));
+        assert!(TextEncoding::default().decode(b"a\xff").1);
+    }
+}
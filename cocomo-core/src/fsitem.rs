@@ -43,7 +43,7 @@ use std::{borrow::Cow, ffi, fmt, fs, path};
 
 use chrono::{DateTime, Local};
 use mimetype_detector::{MimeKind, detect_file};
-use tokio::{fs as async_fs, io};
+use tokio::{fs as async_fs, io, task};
 
 pub type FileType = MimeKind;
 
@@ -91,6 +91,19 @@ impl fmt::Display for FSItemType {
     }
 }
 
+/// Detects the type of the file at `path`.
+///
+/// Detection reads from the file synchronously, so it is run on a thread
+/// dedicated to blocking operations.
+async fn detect_file_type(path: &path::Path) -> FileType {
+    let path = path.to_path_buf();
+    task::spawn_blocking(move || {
+        detect_file(&path).map_or_else(|_| MimeKind::UNKNOWN, |t| t.kind())
+    })
+    .await
+    .unwrap_or(MimeKind::UNKNOWN)
+}
+
 /// A representation of a file system entry.
 ///
 /// `FSItem` bundles the path, metadata, and logical type of a file system
@@ -130,8 +143,7 @@ impl FSItem {
                 item_type: match &meta {
                     m if m.is_dir() => FSItemType::Directory,
                     m if m.is_file() => FSItemType::File {
-                        file_type: detect_file(path)
-                            .map_or_else(|_| MimeKind::UNKNOWN, |t| t.kind()),
+                        file_type: detect_file_type(path).await,
                     },
                     m if m.is_symlink() => FSItemType::SymLink {
                         target: async_fs::read_link(&path)
@@ -210,6 +222,29 @@ impl FSItem {
         matches!(self.item_type, FSItemType::SymLink { .. })
     }
 
+    /// Returns the target path of this item, if it is a symbolic link.
+    pub fn link_target(&self) -> Option<&path::Path> {
+        match &self.item_type {
+            FSItemType::SymLink { target } => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this item and the other item are the same file on
+    /// disk, i.e. hard links to the same inode on the same device.
+    pub fn is_same_file(&self, other: &FSItem) -> bool {
+        #[cfg(unix)]
+        if let (Some(meta), Some(other_meta)) =
+            (&self.metadata, &other.metadata)
+        {
+            use std::os::unix::fs::MetadataExt;
+            return meta.dev() == other_meta.dev()
+                && meta.ino() == other_meta.ino();
+        }
+        let _ = other;
+        false
+    }
+
     /// Follows symbolic links transitively until a non-link target is reached
     /// and returns it as an `FSItem`. For files and directories, returns the
     /// item itself.
@@ -320,6 +355,23 @@ mod tests {
         assert_eq!(file.file_type().unwrap(), FileType::EXECUTABLE);
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_is_same_file() -> std::io::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let file = tmp.path().join("a.txt");
+        let link = tmp.path().join("b.txt");
+        let copy = tmp.path().join("c.txt");
+        async_fs::write(&file, "aaa").await?;
+        async_fs::hard_link(&file, &link).await?;
+        async_fs::copy(&file, &copy).await?;
+        let file = FSItem::new(&file).await;
+        assert!(file.is_same_file(&FSItem::new(&link).await));
+        assert!(!file.is_same_file(&FSItem::new(&copy).await));
+        assert!(!file.is_same_file(&FSItem::new(tmp.path().join("x")).await));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_comparable() {
         let dir1 = FSItem::new("../cocomo-tui").await;
@@ -10,14 +10,33 @@
 //! # File System Operations Module (`fsops`)
 //!
 //! This module provides functions for basic file system operations: copy,
-//! move, delete, and rename.
+//! move, delete, move to trash, and rename.
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fs::Metadata,
+    io,
+    path::{self, Component, Path, PathBuf},
+    sync::Mutex,
+};
 
+use filetime::{FileTime, set_file_times, set_symlink_file_times};
 use thiserror::Error;
-use tokio::fs;
+use tokio::{fs, task};
 
-use crate::fsitem::FSItem;
+use crate::{
+    cancel::CancelToken,
+    content::{content_hash, same_content},
+    copyfile::{copy_content, evict_cached},
+    fsitem::FSItem,
+    journal::{Recorder, Step},
+    plan::Action,
+    trash,
+};
+
+/// Size of the buffer used when copying files.
+pub(crate) const COPY_BUF_SIZE: usize = 1024 * 1024;
 
 /// Error type for file system operations.
 #[derive(Debug, Error)]
@@ -26,6 +45,10 @@ pub enum FsError {
     #[error("source not found: {0}")]
     SourceNotFound(PathBuf),
 
+    /// A compared item exists on the other side only.
+    #[error("item exists on the other side only: {0}")]
+    OtherSideOnly(PathBuf),
+
     /// Destination already exists.
     #[error("destination already exists: {0}")]
     DestinationAlreadyExists(PathBuf),
@@ -37,14 +60,232 @@ pub enum FsError {
     /// Operation not supported for the given item type.
     #[error("operation not supported: {0}")]
     Unsupported(String),
+
+    /// Operation has been cancelled.
+    #[error("operation cancelled")]
+    Cancelled,
+
+    /// Following symbolic links led to a loop.
+    #[error("symbolic link loop: {0}")]
+    SymlinkLoop(PathBuf),
+
+    /// The copy of an item differs from the original.
+    #[error("copy differs from original: {0}")]
+    VerificationFailed(PathBuf),
+
+    /// A directory is in the way of a file or link to be put in its place.
+    #[error("directory in the way: {0}")]
+    DirectoryInTheWay(PathBuf),
+}
+
+/// Policies for handling symbolic links when copying or moving items.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Recreate links with their target unchanged.
+    #[default]
+    Preserve,
+    /// Recreate links, adjusting relative targets pointing outside of the
+    /// copied or moved tree, so that they keep referring to the same item.
+    RewriteRelative,
+    /// Copy the items the links refer to instead of the links. Broken links
+    /// and loops result in an error.
+    Dereference,
+    /// Leave links out.
+    Skip,
+}
+
+/// Policies for handling items already existing at the destination when
+/// copying or moving items.
+///
+/// Directories existing at both places are merged, so only the entries
+/// inside of them can be in conflict. A directory in the way of a file or
+/// link is never replaced because of being older, and only replaced by
+/// overwriting if [`CopyOptions::replace_dirs`] is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing item; a directory only if
+    /// [`CopyOptions::replace_dirs`] is set, otherwise return
+    /// [`FsError::DirectoryInTheWay`] without changing anything.
+    Overwrite,
+    /// Keep the existing item and leave the source item out.
+    Skip,
+    /// Replace the existing item if it is older than the source item and
+    /// not a directory, otherwise leave the source item out.
+    OverwriteIfOlder,
+    /// Keep the existing item and give the new item a name with a number
+    /// appended.
+    KeepBoth,
+    /// Return [`FsError::DestinationAlreadyExists`] without changing
+    /// anything, so that the caller can ask the user how to proceed. An
+    /// item created at the destination while the operation is running
+    /// results in an I/O error of kind `AlreadyExists` instead.
+    #[default]
+    Ask,
+}
+
+/// Options controlling which metadata is preserved, how symbolic links are
+/// handled and how conflicts are resolved when copying or moving items.
+///
+/// By default, all metadata is preserved and links are copied as they are,
+/// so that a copy compares as `Same` to its original, and existing items
+/// are never replaced without asking.
+#[derive(Clone, Debug)]
+pub struct CopyOptions {
+    /// Preserve the access and modification times.
+    pub preserve_times: bool,
+    /// Preserve the permission bits.
+    pub preserve_permissions: bool,
+    /// Preserve owner and group, as far as permitted for the current user.
+    pub preserve_ownership: bool,
+    /// Preserve extended attributes, as far as supported by the destination
+    /// file system.
+    pub preserve_xattrs: bool,
+    /// How to handle symbolic links.
+    pub symlinks: SymlinkPolicy,
+    /// How to handle items already existing at the destination.
+    pub on_conflict: ConflictPolicy,
+    /// Re-read each copied file and compare its checksum to the one of the
+    /// original.
+    pub verify: bool,
+    /// Replace directories in the way of files or links, together with
+    /// their content, when overwriting.
+    pub replace_dirs: bool,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            preserve_times: true,
+            preserve_permissions: true,
+            preserve_ownership: true,
+            preserve_xattrs: true,
+            symlinks: SymlinkPolicy::Preserve,
+            on_conflict: ConflictPolicy::Ask,
+            verify: false,
+            replace_dirs: false,
+        }
+    }
+}
+
+/// Strategies for copying the content of a file, from fastest to slowest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyStrategy {
+    /// The copy shares the data of the original (copy-on-write), which is
+    /// supported by file systems like Btrfs and XFS.
+    Reflink,
+    /// The data is copied inside the kernel via `copy_file_range`.
+    CopyFileRange,
+    /// The data is read into and written from a buffer.
+    Plain,
+}
+
+/// Summary of the strategies used for the files copied by [`copy_item`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CopyReport {
+    /// Number of files cloned by reflink.
+    pub reflinked: usize,
+    /// Number of files copied via `copy_file_range`.
+    pub copied_in_kernel: usize,
+    /// Number of files copied via a buffer.
+    pub copied_plain: usize,
+    /// Number of files recreated as hard links to the copy of another file
+    /// of the same hard-link group.
+    pub hard_linked: usize,
+}
+
+impl CopyReport {
+    /// Returns the total number of files copied.
+    pub const fn files(&self) -> usize {
+        self.reflinked
+            + self.copied_in_kernel
+            + self.copied_plain
+            + self.hard_linked
+    }
+
+    /// Returns the slowest strategy used for any of the files copied, or
+    /// `None` if no file content has been copied.
+    pub const fn strategy(&self) -> Option<CopyStrategy> {
+        if self.copied_plain > 0 {
+            Some(CopyStrategy::Plain)
+        } else if self.copied_in_kernel > 0 {
+            Some(CopyStrategy::CopyFileRange)
+        } else if self.reflinked > 0 {
+            Some(CopyStrategy::Reflink)
+        } else {
+            None
+        }
+    }
+
+    /// Counts a file copied using `strategy`.
+    const fn add(&mut self, strategy: CopyStrategy) {
+        match strategy {
+            CopyStrategy::Reflink => self.reflinked += 1,
+            CopyStrategy::CopyFileRange => self.copied_in_kernel += 1,
+            CopyStrategy::Plain => self.copied_plain += 1,
+        }
+    }
+}
+
+/// Returns `FsError::Cancelled` if cancellation has been requested.
+fn check(cancel: &CancelToken) -> Result<(), FsError> {
+    if cancel.is_cancelled() {
+        Err(FsError::Cancelled)
+    } else {
+        Ok(())
+    }
 }
 
 /// Copies a file or directory from `src` to `dst`.
 ///
-/// If `src` is a directory, it is copied recursively.
-pub async fn copy_item(src: &FSItem, dst: &Path) -> Result<(), FsError> {
-    if src.is_dir() {
-        copy_dir_recursive(src.path(), dst).await?;
+/// If `src` is a directory, it is copied recursively. The metadata selected
+/// by `options` is preserved for all copied files and directories, and
+/// symbolic links are handled according to `options.symlinks`.
+///
+/// Items already existing at the destination are handled according to
+/// `options.on_conflict`. With [`ConflictPolicy::Ask`], and for directories
+/// in the way of files or links with [`ConflictPolicy::Overwrite`], the
+/// whole tree is checked for conflicts before anything gets copied.
+///
+/// If `options.verify` is `true`, each copied file is synced to disk, read
+/// back after being dropped from the page cache, where supported, and its
+/// checksum compared to the one of the original; a mismatch is reported as
+/// [`FsError::VerificationFailed`].
+///
+/// Files hard-linked to each other inside of `src` are copied once and
+/// linked to that copy, so that the hard-link groups are preserved.
+///
+/// Files are written to a temporary file in the destination directory and
+/// renamed once complete, so that an existing file being overwritten is
+/// replaced atomically: the destination always holds either the old or the
+/// new version. If the operation gets cancelled via `cancel`, the temporary
+/// file is removed, so that only completely copied files are left behind.
+/// The same holds for a file failing verification.
+///
+/// The content of each file is copied using the fastest strategy available:
+/// a reflink on copy-on-write file systems, otherwise `copy_file_range` on
+/// Linux, otherwise a plain copy via a buffer. Holes in sparse files are
+/// preserved. The strategies used are returned as a [`CopyReport`].
+pub async fn copy_item(
+    src: &FSItem,
+    dst: &Path,
+    options: &CopyOptions,
+    cancel: &CancelToken,
+) -> Result<CopyReport, FsError> {
+    let tree = Tree::new(src.path(), options, cancel)?;
+    copy_in(src, dst, &tree).await?;
+    Ok(tree.into_report())
+}
+
+/// Copies `src` to `dst` as described for [`copy_item`], performing or
+/// planning the actions according to `tree`.
+pub(crate) async fn copy_in(
+    src: &FSItem,
+    dst: &Path,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    check(tree.cancel)?;
+    let dst = if src.is_dir() {
+        dst.join(src.name())
     } else {
         let mut dst = dst.to_path_buf();
         if dst.is_symlink() {
@@ -53,58 +294,1333 @@ pub async fn copy_item(src: &FSItem, dst: &Path) -> Result<(), FsError> {
         if dst.is_dir() {
             dst = dst.join(src.name());
         }
-        fs::copy(src.path(), dst).await?;
+        dst
+    };
+    check_conflicts(src.path(), &dst, tree).await?;
+    copy_entry(src.path(), &dst, tree, &mut Vec::new()).await
+}
+
+/// The tree of items an operation is applied to.
+pub(crate) struct Tree<'a> {
+    /// Absolute path of the root of the tree.
+    src_root: PathBuf,
+    options: &'a CopyOptions,
+    cancel: &'a CancelToken,
+    /// The actions collected instead of being performed, if the operation
+    /// is only planned.
+    plan: Option<Mutex<Vec<Action>>>,
+    /// The recorder of the changes made, if the operation is journaled.
+    journal: Option<&'a Recorder>,
+    /// The strategies used for copying files.
+    report: Mutex<CopyReport>,
+    /// The copies of the files having further hard links, by device and
+    /// inode of the original.
+    links: Mutex<HashMap<(u64, u64), PathBuf>>,
+}
+
+impl<'a> Tree<'a> {
+    /// Creates a new `Tree` rooted at `src_root`.
+    pub(crate) fn new(
+        src_root: &Path,
+        options: &'a CopyOptions,
+        cancel: &'a CancelToken,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            src_root: normalize(&path::absolute(src_root)?),
+            options,
+            cancel,
+            plan: None,
+            journal: None,
+            report: Mutex::new(CopyReport::default()),
+            links: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Turns the tree into one collecting the actions of an operation
+    /// instead of performing them.
+    pub(crate) fn planning(mut self) -> Self {
+        self.plan = Some(Mutex::new(Vec::new()));
+        self
+    }
+
+    /// Turns the tree into one recording the changes made by an operation
+    /// with `recorder`.
+    pub(crate) fn journaling(mut self, recorder: &'a Recorder) -> Self {
+        self.journal = Some(recorder);
+        self
+    }
+
+    /// Records the step returned by `step`, if the operation is journaled.
+    fn record(&self, step: impl FnOnce() -> Step) {
+        if let Some(journal) = self.journal {
+            journal.record(step());
+        }
+    }
+
+    /// Returns `true` if the operation is only planned.
+    fn is_planning(&self) -> bool {
+        self.plan.is_some()
+    }
+
+    /// Collects the action returned by `action`, if the operation is only
+    /// planned, and returns `true` in that case. Otherwise, the caller has
+    /// to perform the action.
+    fn planned(&self, action: impl FnOnce() -> Action) -> bool {
+        match &self.plan {
+            Some(plan) => {
+                plan.lock().unwrap().push(action());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the collected actions.
+    pub(crate) fn into_actions(self) -> Vec<Action> {
+        self.plan
+            .map(|plan| plan.into_inner().unwrap())
+            .unwrap_or_default()
+    }
+
+    /// Returns the summary of the strategies used for copying files.
+    fn into_report(self) -> CopyReport {
+        self.report.into_inner().unwrap()
+    }
+}
+
+/// Destination of an entry, after resolving conflicts.
+struct Target {
+    /// The path to copy or move the entry to.
+    path: PathBuf,
+    /// `true` if the entry is a directory to be merged into an existing
+    /// directory.
+    merge: bool,
+    /// `true` if the entry is a file to atomically replace an existing
+    /// file.
+    replace: bool,
+}
+
+/// Returns the metadata of the entry `src` of `tree`, following a symbolic
+/// link if links are to be dereferenced, or `None` if it is a link to be
+/// skipped.
+async fn entry_metadata(
+    src: &Path,
+    tree: &Tree<'_>,
+) -> Result<Option<Metadata>, FsError> {
+    let metadata = fs::symlink_metadata(src).await?;
+    if !metadata.is_symlink() {
+        return Ok(Some(metadata));
+    }
+    match tree.options.symlinks {
+        SymlinkPolicy::Skip => Ok(None),
+        SymlinkPolicy::Dereference => Ok(Some(
+            fs::metadata(src)
+                .await
+                .map_err(|err| link_error(src, err))?,
+        )),
+        _ => Ok(Some(metadata)),
+    }
+}
+
+/// Returns [`FsError::DestinationAlreadyExists`] for the first conflict
+/// found when copying or moving the entry `src` of `tree` to `dst`, if
+/// conflicts are to be resolved by asking, or
+/// [`FsError::DirectoryInTheWay`] for the first directory in the way of a
+/// file or link, if it would be overwritten without being confirmed.
+async fn check_conflicts(
+    src: &Path,
+    dst: &Path,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    match tree.options.on_conflict {
+        ConflictPolicy::Ask => {
+            match find_conflict(src, dst, false, tree).await? {
+                Some(path) => Err(FsError::DestinationAlreadyExists(path)),
+                None => Ok(()),
+            }
+        }
+        ConflictPolicy::Overwrite if !tree.options.replace_dirs => {
+            match find_conflict(src, dst, true, tree).await? {
+                Some(path) => Err(FsError::DirectoryInTheWay(path)),
+                None => Ok(()),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the first path below `dst` conflicting with the entry `src` of
+/// `tree` or one of its descendants; only directories in the way of a file
+/// or link if `dirs_only` is `true`.
+async fn find_conflict(
+    src: &Path,
+    dst: &Path,
+    dirs_only: bool,
+    tree: &Tree<'_>,
+) -> Result<Option<PathBuf>, FsError> {
+    check(tree.cancel)?;
+    let Some(metadata) = entry_metadata(src, tree).await? else {
+        return Ok(None);
+    };
+    let Ok(dst_metadata) = fs::symlink_metadata(dst).await else {
+        return Ok(None);
+    };
+    if !(metadata.is_dir() && dst_metadata.is_dir()) {
+        return Ok((!dirs_only || dst_metadata.is_dir()).then(|| dst.into()));
+    }
+    let mut entries = fs::read_dir(src).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let dst_path = dst.join(entry.file_name());
+        if let Some(path) =
+            Box::pin(find_conflict(&entry.path(), &dst_path, dirs_only, tree))
+                .await?
+        {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves a conflict between the entry `src` with the given `metadata`
+/// and an item existing at `dst`, according to the options of `tree`.
+///
+/// Returns the target to copy or move the entry to, or `None` if the entry
+/// is to be left out.
+async fn resolve_conflict(
+    src: &Path,
+    dst: &Path,
+    metadata: &Metadata,
+    tree: &Tree<'_>,
+) -> Result<Option<Target>, FsError> {
+    let free = |path: PathBuf| {
+        Ok(Some(Target {
+            path,
+            merge: false,
+            replace: false,
+        }))
+    };
+    let Ok(dst_metadata) = fs::symlink_metadata(dst).await else {
+        return free(dst.into());
+    };
+    if metadata.is_dir() && dst_metadata.is_dir() {
+        return Ok(Some(Target {
+            path: dst.into(),
+            merge: true,
+            replace: false,
+        }));
+    }
+    let overwrite = match tree.options.on_conflict {
+        ConflictPolicy::Overwrite
+            if dst_metadata.is_dir() && !tree.options.replace_dirs =>
+        {
+            // The directories in the way have been checked before anything
+            // got changed, so this one has been created since
+            return Err(FsError::DirectoryInTheWay(dst.into()));
+        }
+        ConflictPolicy::Overwrite => true,
+        ConflictPolicy::Skip => false,
+        // A directory is not replaced because of its modification time
+        ConflictPolicy::OverwriteIfOlder if dst_metadata.is_dir() => false,
+        ConflictPolicy::OverwriteIfOlder => {
+            match (dst_metadata.modified(), metadata.modified()) {
+                (Ok(dst_time), Ok(src_time)) => dst_time < src_time,
+                _ => false,
+            }
+        }
+        ConflictPolicy::KeepBoth => {
+            return free(numbered_path(dst, !metadata.is_dir()).await);
+        }
+        ConflictPolicy::Ask => {
+            // The tree has been checked for conflicts before anything got
+            // changed, so the item has been created since; other entries
+            // may already have been copied or moved, so the operation
+            // can't simply be retried with another policy
+            return Err(FsError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} has been created meanwhile", dst.display()),
+            )));
+        }
+    };
+    if !overwrite {
+        tree.planned(|| Action::Skip {
+            src: src.into(),
+            dst: dst.into(),
+        });
+        return Ok(None);
+    }
+    if metadata.is_file() && !dst_metadata.is_dir() {
+        // The existing item gets replaced when the entry is in place
+        if !tree.planned(|| Action::DeleteFile {
+            path: dst.into(),
+            size: dst_metadata.len(),
+        }) {
+            save(dst, tree).await?;
+        }
+        return Ok(Some(Target {
+            path: dst.into(),
+            merge: false,
+            replace: true,
+        }));
+    }
+    if dst_metadata.is_dir() {
+        remove_dir_recursive(dst, tree).await?;
+    } else {
+        remove_file(dst, &dst_metadata, tree).await?;
+    }
+    free(dst.into())
+}
+
+/// Returns the first non-existing path derived from `path` by appending
+/// " (1)", " (2)", … to its name, before the extension if `keep_extension`
+/// is `true`.
+async fn numbered_path(path: &Path, keep_extension: bool) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    let mut n = 1_u32;
+    loop {
+        let candidate =
+            path.with_file_name(numbered_name(name, n, keep_extension));
+        if fs::symlink_metadata(&candidate).await.is_err() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Returns `name` with the number `n` appended in parentheses, before the
+/// extension, if `keep_extension` is `true`.
+pub(crate) fn numbered_name(
+    name: &OsStr,
+    n: u32,
+    keep_extension: bool,
+) -> OsString {
+    let path = Path::new(name);
+    let (stem, extension) = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) if keep_extension => {
+            (stem, Some(extension))
+        }
+        _ => (name, None),
+    };
+    let mut name = stem.to_os_string();
+    name.push(format!(" ({})", n));
+    if let Some(extension) = extension {
+        name.push(".");
+        name.push(extension);
+    }
+    name
+}
+
+/// Copies the entry `src` of `tree` to `dst`.
+///
+/// `ancestors` holds the real paths of the directories currently being
+/// copied, in order to detect loops caused by dereferencing symbolic links.
+async fn copy_entry(
+    src: &Path,
+    dst: &Path,
+    tree: &Tree<'_>,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<(), FsError> {
+    check(tree.cancel)?;
+    // Get the metadata before reading, which may update the access time
+    let Some(metadata) = entry_metadata(src, tree).await? else {
+        return Ok(());
+    };
+    let Some(target) = resolve_conflict(src, dst, &metadata, tree).await?
+    else {
+        return Ok(());
+    };
+    copy_to(src, &target, metadata, tree, ancestors).await
+}
+
+/// Copies the entry `src` of `tree` with the given `metadata` to `target`,
+/// with conflicts already resolved.
+async fn copy_to(
+    src: &Path,
+    target: &Target,
+    metadata: Metadata,
+    tree: &Tree<'_>,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<(), FsError> {
+    let dst = target.path.as_path();
+    if metadata.is_symlink() {
+        let rewrite = tree.options.symlinks == SymlinkPolicy::RewriteRelative;
+        copy_link(src, dst, metadata, rewrite, tree).await
+    } else if metadata.is_dir() {
+        copy_dir(src, dst, target.merge, metadata, tree, ancestors).await
+    } else if metadata.is_file() {
+        copy_file(src, dst, target.replace, metadata, tree).await
+    } else {
+        Err(FsError::Unsupported(format!(
+            "copying special file {}",
+            src.display()
+        )))
+    }
+}
+
+/// Converts an error raised when following the symbolic link `link`.
+fn link_error(link: &Path, err: io::Error) -> FsError {
+    match err.kind() {
+        io::ErrorKind::NotFound => FsError::SourceNotFound(link.into()),
+        #[cfg(unix)]
+        _ if err.raw_os_error() == Some(libc::ELOOP) => {
+            FsError::SymlinkLoop(link.into())
+        }
+        _ => err.into(),
+    }
+}
+
+/// Copies the content of a file and the metadata selected by the options
+/// of `tree`.
+///
+/// The copy is written to a temporary file next to `dst`, which is renamed
+/// to `dst` once complete, so that `dst` never holds a partial copy. If
+/// `replace` is `true`, an existing file at `dst` is replaced atomically,
+/// syncing the new file to disk before and its directory after the rename.
+///
+/// If the copy fails or gets cancelled, the temporary file is removed.
+///
+/// If another file of the hard-link group of `src` has already been copied
+/// as part of `tree`, `dst` is linked to that copy instead.
+async fn copy_file(
+    src: &Path,
+    dst: &Path,
+    replace: bool,
+    metadata: Metadata,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    let inode = linked_inode(&metadata);
+    let first = inode
+        .and_then(|inode| tree.links.lock().unwrap().get(&inode).cloned());
+    // Fall back to copying, if linking fails
+    if let Some(first) = first
+        && link_file(&first, dst, replace, tree).await.is_ok()
+    {
+        return Ok(());
+    }
+    let remember = || {
+        if let Some(inode) = inode {
+            tree.links.lock().unwrap().insert(inode, dst.into());
+        }
+    };
+    if tree.planned(|| Action::CopyFile {
+        src: src.into(),
+        dst: dst.into(),
+        size: metadata.len(),
+    }) {
+        remember();
+        return Ok(());
+    }
+    let (tmp, writer) = create_temp_file(dst).await?;
+    let result = async {
+        let strategy = {
+            let (src, tmp) = (src.to_path_buf(), tmp.clone());
+            let cancel = tree.cancel.clone();
+            task::spawn_blocking(move || copy_content(&src, &tmp, &cancel))
+                .await
+                .map_err(io::Error::other)?
+                .map_err(|err| cancelled_or(err, tree))?
+        };
+        if tree.options.verify || replace {
+            writer.sync_all().await?;
+        }
+        let writer = writer.into_std().await;
+        if tree.options.verify {
+            // Read the copy back from the device, not from the cache
+            evict_cached(&writer)?;
+        }
+        drop(writer);
+        if tree.options.verify {
+            let hash = |path| async move {
+                content_hash(path, |_| tree.cancel.check())
+                    .await
+                    .map_err(|err| cancelled_or(err, tree))
+            };
+            if hash(&tmp).await? != hash(src).await? {
+                return Err(FsError::VerificationFailed(dst.into()));
+            }
+        }
+        tree.report.lock().unwrap().add(strategy);
+        copy_metadata(src, &tmp, metadata, tree).await?;
+        fs::rename(&tmp, dst).await?;
+        Ok(())
+    }
+    .await;
+    if result.is_err() {
+        // Don't leave a partial copy behind
+        let _ = fs::remove_file(&tmp).await;
+        return result;
+    }
+    tree.record(|| Step::Created(dst.into()));
+    remember();
+    if replace {
+        sync_dir(dst).await?;
+    }
+    Ok(())
+}
+
+/// Creates `dst` as a hard link to `first`, the copy of another file of the
+/// same hard-link group. If `replace` is `true`, an existing file at `dst`
+/// is replaced atomically.
+async fn link_file(
+    first: &Path,
+    dst: &Path,
+    replace: bool,
+    tree: &Tree<'_>,
+) -> io::Result<()> {
+    if tree.planned(|| Action::CreateHardLink {
+        path: dst.into(),
+        target: first.into(),
+    }) {
+        return Ok(());
+    }
+    if replace {
+        let mut n = 0_u32;
+        let tmp = loop {
+            let tmp = temp_path(dst, n);
+            match fs::hard_link(first, &tmp).await {
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                }
+                result => break result.map(|_| tmp)?,
+            }
+        };
+        if let Err(err) = fs::rename(&tmp, dst).await {
+            let _ = fs::remove_file(&tmp).await;
+            return Err(err);
+        }
+    } else {
+        fs::hard_link(first, dst).await?;
     }
+    tree.record(|| Step::Created(dst.into()));
+    tree.report.lock().unwrap().hard_linked += 1;
     Ok(())
 }
 
-/// Recursively copies a directory.
-async fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<(), FsError> {
-    let mut dst_path = dst.join(src.file_name().get_or_insert_default());
-    fs::create_dir(&dst_path).await.unwrap_or(());
+/// Returns the device and inode of the file with the given `metadata`, if
+/// it has further hard links.
+#[cfg(unix)]
+fn linked_inode(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+/// Returns the device and inode of the file with the given `metadata`, if
+/// it has further hard links.
+#[cfg(not(unix))]
+fn linked_inode(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Returns `FsError::Cancelled` if cancellation of the operation on `tree`
+/// has been requested, otherwise `err`.
+fn cancelled_or(err: io::Error, tree: &Tree<'_>) -> FsError {
+    match check(tree.cancel) {
+        Err(cancelled) => cancelled,
+        Ok(()) => err.into(),
+    }
+}
+
+/// Returns the path of the `n`th candidate for a temporary file in the
+/// directory of `path`.
+fn temp_path(path: &Path, n: u32) -> PathBuf {
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(path.file_name().unwrap_or_default());
+    tmp_name.push(format!(".{}.cocomo-tmp", n));
+    path.with_file_name(tmp_name)
+}
+
+/// Creates a new temporary file in the directory of `path` and returns its
+/// path and handle.
+pub(crate) async fn create_temp_file(
+    path: &Path,
+) -> io::Result<(PathBuf, fs::File)> {
+    let mut n = 0_u32;
+    loop {
+        let tmp = temp_path(path, n);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+            .await
+        {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            result => return Ok((tmp, result?)),
+        }
+    }
+}
+
+/// Syncs the directory holding `path` to disk, making a rename of `path`
+/// durable.
+#[cfg(unix)]
+pub(crate) async fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => {
+            fs::File::open(dir).await?.sync_all().await
+        }
+        _ => fs::File::open(".").await?.sync_all().await,
+    }
+}
+
+/// Syncs the directory holding `path` to disk, making a rename of `path`
+/// durable.
+#[cfg(not(unix))]
+pub(crate) async fn sync_dir(_path: &Path) -> io::Result<()> {
+    // Directories can't be opened as files on other platforms
+    Ok(())
+}
+
+/// Recursively copies the directory `src` to `dst`, creating `dst` unless
+/// the entries are to be merged into it, and preserves the metadata
+/// selected by the options of `tree`.
+async fn copy_dir(
+    src: &Path,
+    dst: &Path,
+    merge: bool,
+    metadata: Metadata,
+    tree: &Tree<'_>,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<(), FsError> {
+    let real_path = fs::canonicalize(src).await?;
+    if ancestors.contains(&real_path) {
+        return Err(FsError::SymlinkLoop(src.into()));
+    }
+    if !merge && !tree.planned(|| Action::CreateDir { path: dst.into() }) {
+        fs::create_dir(dst).await?;
+        tree.record(|| Step::CreatedDir(dst.into()));
+    }
+    ancestors.push(real_path);
     let mut entries = fs::read_dir(src).await?;
     while let Some(entry) = entries.next_entry().await? {
-        let file_type = entry.file_type().await?;
-        let src_path = entry.path();
-        dst_path = dst_path.join(entry.file_name());
-        if file_type.is_dir() {
-            fs::create_dir(&dst_path).await.unwrap_or(());
-            Box::pin(copy_dir_recursive(&src_path, &dst_path)).await?;
-        } else {
-            fs::copy(&src_path, &dst_path).await?;
+        let dst_path = dst.join(entry.file_name());
+        Box::pin(copy_entry(&entry.path(), &dst_path, tree, ancestors))
+            .await?;
+    }
+    ancestors.pop();
+    // The times have to be set after the entries have been created, and the
+    // permissions may prevent creating entries, so both are set last.
+    copy_metadata(src, dst, metadata, tree).await
+}
+
+/// Copies the symbolic link `src` to `dst`.
+///
+/// If `rewrite` is `true`, a relative target pointing outside of `tree` is
+/// adjusted so that the copy refers to the same item as the original.
+async fn copy_link(
+    src: &Path,
+    dst: &Path,
+    metadata: Metadata,
+    rewrite: bool,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    let mut target = fs::read_link(src).await?;
+    if rewrite {
+        target = rewrite_link_target(src, &target, &tree.src_root, dst)?;
+    }
+    if tree.planned(|| Action::CreateLink {
+        path: dst.into(),
+        target: target.clone(),
+    }) {
+        return Ok(());
+    }
+    symlink(&target, dst).await?;
+    tree.record(|| Step::Created(dst.into()));
+    copy_metadata(src, dst, metadata, tree).await
+}
+
+/// Creates a symbolic link at `link` pointing to `target`.
+#[cfg(unix)]
+async fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    fs::symlink(target, link).await
+}
+
+/// Creates a symbolic link at `link` pointing to `target`.
+#[cfg(not(unix))]
+async fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symbolic links are not supported",
+    ))
+}
+
+/// Returns the target for a copy of the symbolic link `link` at `new_link`.
+///
+/// Absolute targets and relative targets inside of `src_root` are kept,
+/// other relative targets are made relative to the location of `new_link`.
+fn rewrite_link_target(
+    link: &Path,
+    target: &Path,
+    src_root: &Path,
+    new_link: &Path,
+) -> io::Result<PathBuf> {
+    if target.is_absolute() {
+        return Ok(target.into());
+    }
+    let link = path::absolute(link)?;
+    let link_dir = link.parent().unwrap_or(&link);
+    let resolved = normalize(&link_dir.join(target));
+    if resolved.starts_with(src_root) {
+        return Ok(target.into());
+    }
+    let new_link = normalize(&path::absolute(new_link)?);
+    let new_link_dir = new_link.parent().unwrap_or(&new_link);
+    Ok(relative_path(new_link_dir, &resolved))
+}
+
+/// Lexically removes `.` and `..` components from `path`.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Returns the relative path leading from the directory `from` to `to`, or
+/// `.` if both are the same.
+///
+/// Both paths must be normalized and either absolute or relative to the
+/// same directory.
+pub fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative: PathBuf = from
+        .components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .collect();
+    relative.extend(to.components().skip(common));
+    if relative.as_os_str().is_empty() {
+        relative.push(Component::CurDir);
+    }
+    relative
+}
+
+/// Applies the metadata of `src` selected by the options of `tree` to
+/// `dst`.
+///
+/// Failures caused by missing privileges (when changing the owner or
+/// reading extended attributes) or by lack of support in the file systems
+/// (when listing or setting extended attributes) are ignored.
+async fn copy_metadata(
+    src: &Path,
+    dst: &Path,
+    metadata: Metadata,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    if tree.is_planning() {
+        return Ok(());
+    }
+    let options = tree.options.clone();
+    let src = src.to_path_buf();
+    let dst = dst.to_path_buf();
+    task::spawn_blocking(move || {
+        copy_metadata_blocking(&src, &dst, &metadata, &options)
+    })
+    .await
+    .map_err(io::Error::other)??;
+    Ok(())
+}
+
+/// Blocking implementation of [`copy_metadata`].
+///
+/// If `metadata` belongs to a symbolic link, it is applied to the link at
+/// `dst` itself; otherwise links at `src` are followed.
+fn copy_metadata_blocking(
+    src: &Path,
+    dst: &Path,
+    metadata: &Metadata,
+    options: &CopyOptions,
+) -> io::Result<()> {
+    let is_link = metadata.is_symlink();
+    // Changing the owner may reset the set-user-ID and set-group-ID bits, so
+    // it has to precede setting the permissions.
+    #[cfg(unix)]
+    if options.preserve_ownership {
+        use std::os::unix::fs::{MetadataExt, chown, lchown};
+        let (uid, gid) = (Some(metadata.uid()), Some(metadata.gid()));
+        ignore_errors(
+            if is_link {
+                lchown(dst, uid, gid)
+            } else {
+                chown(dst, uid, gid)
+            },
+            &[io::ErrorKind::PermissionDenied],
+        )?;
+    }
+    // Extended attributes and permissions of links are not used
+    if is_link {
+        if options.preserve_times {
+            set_symlink_file_times(
+                dst,
+                FileTime::from_last_access_time(metadata),
+                FileTime::from_last_modification_time(metadata),
+            )?;
+        }
+        return Ok(());
+    }
+    #[cfg(unix)]
+    if options.preserve_xattrs {
+        let unsupported =
+            [io::ErrorKind::PermissionDenied, io::ErrorKind::Unsupported];
+        for name in ignore_errors(xattr::list_deref(src), &unsupported)?
+            .into_iter()
+            .flatten()
+        {
+            // Attributes which can't be read are skipped as well
+            if let Some(value) =
+                ignore_errors(xattr::get_deref(src, &name), &unsupported)?
+                    .flatten()
+            {
+                ignore_errors(xattr::set(dst, &name, &value), &unsupported)?;
+            }
         }
     }
+    if options.preserve_times {
+        set_file_times(
+            dst,
+            FileTime::from_last_access_time(metadata),
+            FileTime::from_last_modification_time(metadata),
+        )?;
+    }
+    if options.preserve_permissions {
+        std::fs::set_permissions(dst, metadata.permissions())?;
+    }
     Ok(())
 }
 
+/// Turns errors of one of the given `kinds` into `Ok(None)`.
+fn ignore_errors<T>(
+    result: io::Result<T>,
+    kinds: &[io::ErrorKind],
+) -> io::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if kinds.contains(&err.kind()) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
 /// Moves a file or directory from `src` to `dst`.
-pub async fn move_item(src: &FSItem, dst: &Path) -> Result<(), FsError> {
+///
+/// Symbolic links are handled according to `options.symlinks`. Unless they
+/// are to be preserved, a directory is moved entry by entry, applying the
+/// metadata selected by `options` to the recreated directories. Skipped
+/// links are left behind in the source directory.
+///
+/// Items already existing at the destination are handled according to
+/// `options.on_conflict`, like in [`copy_item`].
+///
+/// Items which can't be renamed because the destination is on another file
+/// system are copied with the metadata selected by `options`, verified and
+/// deleted afterwards. If copying or verifying fails, the copy is removed
+/// again, so that the source stays untouched.
+pub async fn move_item(
+    src: &FSItem,
+    dst: &Path,
+    options: &CopyOptions,
+    cancel: &CancelToken,
+) -> Result<(), FsError> {
+    move_in(src, dst, &Tree::new(src.path(), options, cancel)?).await
+}
+
+/// Moves `src` to `dst` as described for [`move_item`], performing or
+/// planning the actions according to `tree`.
+pub(crate) async fn move_in(
+    src: &FSItem,
+    dst: &Path,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    check(tree.cancel)?;
     let mut dst = dst.to_path_buf();
     if dst.is_dir() {
         dst = dst.join(src.name());
     }
-    fs::rename(src.path(), &dst).await?;
+    if src.is_dir()
+        && normalize(&path::absolute(&dst)?).starts_with(&tree.src_root)
+    {
+        return Err(FsError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot move a directory into itself",
+        )));
+    }
+    check_conflicts(src.path(), &dst, tree).await?;
+    move_entry(src.path(), &dst, tree).await?;
     Ok(())
 }
 
-/// Deletes a file or directory.
-pub async fn delete_item(item: &FSItem) -> Result<(), FsError> {
-    if item.is_dir() {
-        fs::remove_dir_all(item.path()).await?;
+/// Moves the entry `src` of `tree` to `dst`, handling symbolic links and
+/// conflicts according to the options of `tree`.
+///
+/// Returns `false` if the entry, or some entry below it, has been left
+/// behind.
+async fn move_entry(
+    src: &Path,
+    dst: &Path,
+    tree: &Tree<'_>,
+) -> Result<bool, FsError> {
+    check(tree.cancel)?;
+    let Some(metadata) = entry_metadata(src, tree).await? else {
+        // Skipped links are left behind
+        return Ok(false);
+    };
+    let Some(target) = resolve_conflict(src, dst, &metadata, tree).await?
+    else {
+        return Ok(false);
+    };
+    let dst = target.path.as_path();
+    let link_metadata = fs::symlink_metadata(src).await?;
+    if link_metadata.is_symlink() {
+        if tree.options.symlinks == SymlinkPolicy::Preserve {
+            rename_entry(src, &target, tree).await?;
+        } else {
+            copy_to(src, &target, metadata, tree, &mut Vec::new()).await?;
+            remove_file(src, &link_metadata, tree).await?;
+        }
+    } else if link_metadata.is_dir() {
+        if !target.merge && tree.options.symlinks == SymlinkPolicy::Preserve {
+            rename_entry(src, &target, tree).await?;
+            return Ok(true);
+        }
+        if !target.merge
+            && !tree.planned(|| Action::CreateDir { path: dst.into() })
+        {
+            fs::create_dir(dst).await?;
+            tree.record(|| Step::CreatedDir(dst.into()));
+        }
+        let mut moved_all = true;
+        let mut entries = fs::read_dir(src).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let dst_path = dst.join(entry.file_name());
+            moved_all &=
+                Box::pin(move_entry(&entry.path(), &dst_path, tree)).await?;
+        }
+        copy_metadata(src, dst, link_metadata, tree).await?;
+        if !moved_all {
+            return Ok(false);
+        }
+        if !tree.planned(|| Action::DeleteDir { path: src.into() })
+            && !back_up(src, tree).await?
+        {
+            fs::remove_dir(src).await?;
+        }
     } else {
-        fs::remove_file(item.path()).await?;
+        rename_entry(src, &target, tree).await?;
     }
+    Ok(true)
+}
+
+/// Renames the entry `src` of `tree` to `target`, falling back to copying
+/// and deleting it if `target` is on another file system.
+async fn rename_entry(
+    src: &Path,
+    target: &Target,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    let dst = target.path.as_path();
+    if tree.is_planning() && !same_device(src, dst).await {
+        return move_across(src, target, tree).await;
+    }
+    if tree.planned(|| Action::Rename {
+        src: src.into(),
+        dst: dst.into(),
+    }) {
+        return Ok(());
+    }
+    match fs::rename(src, dst).await {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            move_across(src, target, tree).await
+        }
+        result => {
+            result?;
+            tree.record(|| Step::Renamed {
+                src: src.into(),
+                dst: dst.into(),
+            });
+            Ok(())
+        }
+    }
+}
+
+/// Returns `false` if the item at `src` and the directory to hold `dst`
+/// are known to be on different file systems.
+async fn same_device(src: &Path, dst: &Path) -> bool {
+    let dst_dir = match dst.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    match (fs::symlink_metadata(src).await, fs::metadata(dst_dir).await) {
+        (Ok(src_metadata), Ok(dir_metadata)) => {
+            device(&src_metadata) == device(&dir_metadata)
+        }
+        _ => true,
+    }
+}
+
+/// Returns the id of the device holding the item with `metadata`.
+#[cfg(unix)]
+pub(crate) fn device(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.dev()
+}
+
+/// Returns the id of the device holding the item with `metadata`.
+#[cfg(not(unix))]
+pub(crate) fn device(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// Moves the entry `src` of `tree` to `dst` on another file system by
+/// copying it with the metadata selected by the options of `tree`,
+/// verifying the copy and deleting `src` afterwards.
+///
+/// If copying or verifying fails, the copy is removed again, leaving `src`
+/// untouched; a file replaced by the copy is restored. If deleting `src`
+/// fails, the verified copy is kept.
+async fn move_across(
+    src: &Path,
+    target: &Target,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    let dst = target.path.as_path();
+    // The replaced file is kept until the copy has been verified
+    let replaced = if target.replace && !tree.is_planning() {
+        Some(keep_replaced(dst).await?)
+    } else {
+        None
+    };
+    let copied = async {
+        let Some(metadata) = entry_metadata(src, tree).await? else {
+            return Ok(());
+        };
+        copy_to(src, target, metadata, tree, &mut Vec::new()).await?;
+        if !tree.is_planning() {
+            verify_copy(src, dst, tree.cancel).await?;
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(err) = copied {
+        // Roll back; the source is still complete
+        if let Some(replaced) = replaced {
+            let _ = fs::rename(&replaced, dst).await;
+        } else if !tree.is_planning() {
+            let _ = match fs::symlink_metadata(dst).await {
+                Ok(metadata) if metadata.is_dir() => {
+                    fs::remove_dir_all(dst).await
+                }
+                _ => fs::remove_file(dst).await,
+            };
+        }
+        return Err(err);
+    }
+    if let Some(replaced) = replaced {
+        fs::remove_file(&replaced).await?;
+    }
+    let metadata = fs::symlink_metadata(src).await?;
+    if metadata.is_dir() {
+        remove_dir_recursive(src, tree).await
+    } else {
+        remove_file(src, &metadata, tree).await
+    }
+}
+
+/// Links the file at `path`, which is going to be replaced, to a temporary
+/// path next to it, or copies it there if it can't be linked, and returns
+/// the temporary path.
+async fn keep_replaced(path: &Path) -> io::Result<PathBuf> {
+    let mut n = 0_u32;
+    loop {
+        let tmp = temp_path(path, n);
+        match fs::hard_link(path, &tmp).await {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(_) => {
+                let (tmp, _) = create_temp_file(path).await?;
+                fs::copy(path, &tmp).await?;
+                return Ok(tmp);
+            }
+            Ok(()) => return Ok(tmp),
+        }
+    }
+}
+
+/// Checks that `copy` is a complete copy of the entry `original`: same type,
+/// same content for files, same target for links and, recursively, the same
+/// entries for directories. Copied files are synced to disk and, where
+/// supported, dropped from the page cache before being read back.
+async fn verify_copy(
+    original: &Path,
+    copy: &Path,
+    cancel: &CancelToken,
+) -> Result<(), FsError> {
+    if verify_entry(original, copy, cancel).await? {
+        Ok(())
+    } else {
+        Err(FsError::VerificationFailed(copy.into()))
+    }
+}
+
+/// Returns `true` if `copy` is a complete copy of the entry `original`, as
+/// described for [`verify_copy`].
+async fn verify_entry(
+    original: &Path,
+    copy: &Path,
+    cancel: &CancelToken,
+) -> Result<bool, FsError> {
+    check(cancel)?;
+    let metadata = fs::symlink_metadata(original).await?;
+    let Ok(copy_metadata) = fs::symlink_metadata(copy).await else {
+        return Ok(false);
+    };
+    if metadata.is_symlink() {
+        return Ok(copy_metadata.is_symlink()
+            && fs::read_link(original).await? == fs::read_link(copy).await?);
+    }
+    if metadata.is_dir() {
+        if !copy_metadata.is_dir() {
+            return Ok(false);
+        }
+        let mut n_entries = 0_usize;
+        let mut entries = fs::read_dir(original).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let copy_path = copy.join(entry.file_name());
+            if !Box::pin(verify_entry(&entry.path(), &copy_path, cancel))
+                .await?
+            {
+                return Ok(false);
+            }
+            n_entries += 1;
+        }
+        let mut copy_entries = fs::read_dir(copy).await?;
+        while copy_entries.next_entry().await?.is_some() {
+            n_entries = match n_entries.checked_sub(1) {
+                Some(n) => n,
+                None => return Ok(false),
+            };
+        }
+        return Ok(n_entries == 0);
+    }
+    if !copy_metadata.is_file() || metadata.len() != copy_metadata.len() {
+        return Ok(false);
+    }
+    // Read the copy back from the device, not from the cache
+    let file = fs::File::open(copy).await?;
+    file.sync_all().await?;
+    evict_cached(&file.into_std().await)?;
+    match same_content(original, copy, |_| cancel.check()).await {
+        Err(_) if cancel.is_cancelled() => Err(FsError::Cancelled),
+        result => Ok(result?),
+    }
+}
+
+/// Deletes a file or directory permanently.
+///
+/// See [`trash_item`] for a recoverable alternative. If the operation gets
+/// cancelled via `cancel`, the entries deleted so far stay deleted, all others
+/// are left untouched.
+pub async fn delete_item(
+    item: &FSItem,
+    cancel: &CancelToken,
+) -> Result<(), FsError> {
+    let options = CopyOptions::default();
+    delete_in(item, &Tree::new(item.path(), &options, cancel)?).await
+}
+
+/// Deletes `item`, performing or planning the actions according to `tree`.
+pub(crate) async fn delete_in(
+    item: &FSItem,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    check(tree.cancel)?;
+    let metadata = fs::symlink_metadata(item.path()).await?;
+    if metadata.is_dir() {
+        remove_dir_recursive(item.path(), tree).await
+    } else {
+        remove_file(item.path(), &metadata, tree).await
+    }
+}
+
+/// Moves a file or directory to the trash, following the freedesktop.org
+/// Trash specification, so that it can be restored later.
+///
+/// Items are moved to the home trash (`$XDG_DATA_HOME/Trash`), if residing
+/// on the same file system, or to the trash directory at the top of their
+/// mount point otherwise.
+pub async fn trash_item(
+    item: &FSItem,
+    cancel: &CancelToken,
+) -> Result<(), FsError> {
+    let options = CopyOptions::default();
+    trash_in(item, &Tree::new(item.path(), &options, cancel)?).await
+}
+
+/// Moves `item` to the trash, performing or planning the action according
+/// to `tree`.
+pub(crate) async fn trash_in(
+    item: &FSItem,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    check(tree.cancel)?;
+    if fs::symlink_metadata(item.path()).await.is_err() {
+        return Err(FsError::SourceNotFound(item.path().clone()));
+    }
+    if !tree.planned(|| Action::Trash {
+        path: item.path().clone(),
+    }) {
+        let trash_dir =
+            trash::trash_dir(item.path(), &trash::home_trash()?).await?;
+        let trashed = trash::move_to_trash(item.path(), &trash_dir).await?;
+        tree.record(|| Step::Trashed {
+            path: item.path().clone(),
+            trashed,
+        });
+    }
+    Ok(())
+}
+
+/// Deletes the file or link at `path` with the given `metadata`.
+async fn remove_file(
+    path: &Path,
+    metadata: &Metadata,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    if !tree.planned(|| Action::DeleteFile {
+        path: path.into(),
+        size: metadata.len(),
+    }) && !back_up(path, tree).await?
+    {
+        fs::remove_file(path).await?;
+    }
+    Ok(())
+}
+
+/// Recursively deletes a directory.
+async fn remove_dir_recursive(
+    path: &Path,
+    tree: &Tree<'_>,
+) -> Result<(), FsError> {
+    if back_up(path, tree).await? {
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        check(tree.cancel)?;
+        let metadata = fs::symlink_metadata(entry.path()).await?;
+        if metadata.is_dir() {
+            Box::pin(remove_dir_recursive(&entry.path(), tree)).await?;
+        } else {
+            remove_file(&entry.path(), &metadata, tree).await?;
+        }
+    }
+    if !tree.planned(|| Action::DeleteDir { path: path.into() }) {
+        fs::remove_dir(path).await?;
+    }
+    Ok(())
+}
+
+/// Moves the item at `path` to the backup directory instead of deleting
+/// it, if the operation on `tree` is journaled, and returns `true` in that
+/// case. Returns `false` as well, recording the item as unrecoverable, if
+/// no backup can be kept on its file system.
+async fn back_up(path: &Path, tree: &Tree<'_>) -> Result<bool, FsError> {
+    let Some(journal) = tree.journal else {
+        return Ok(false);
+    };
+    let Some(backup) = journal.backup_path(path).await else {
+        journal.record_unrecoverable(path);
+        return Ok(false);
+    };
+    Box::pin(transfer(path, &backup, tree.cancel)).await?;
+    journal.record(Step::Removed {
+        path: path.into(),
+        backup,
+    });
+    Ok(true)
+}
+
+/// Keeps a copy of the file at `path` in the backup directory before it gets
+/// replaced, if the operation on `tree` is journaled, or records the file as
+/// unrecoverable if no backup can be kept on its file system.
+async fn save(path: &Path, tree: &Tree<'_>) -> Result<(), FsError> {
+    let Some(journal) = tree.journal else {
+        return Ok(());
+    };
+    let Some(backup) = journal.backup_path(path).await else {
+        journal.record_unrecoverable(path);
+        return Ok(());
+    };
+    // The file is replaced by a new one, so linking it is sufficient
+    if fs::hard_link(path, &backup).await.is_err() {
+        let options = CopyOptions::default();
+        let plain = Tree::new(path, &options, tree.cancel)?;
+        Box::pin(copy_entry(path, &backup, &plain, &mut Vec::new())).await?;
+    }
+    journal.record(Step::Saved {
+        path: path.into(),
+        backup,
+    });
     Ok(())
 }
 
+/// Renames the item at `from` to `to`, or copies it preserving all metadata
+/// and deletes the original, if `to` is on another file system.
+pub(crate) async fn transfer(
+    from: &Path,
+    to: &Path,
+    cancel: &CancelToken,
+) -> Result<(), FsError> {
+    match fs::rename(from, to).await {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            let options = CopyOptions::default();
+            let target = Target {
+                path: to.into(),
+                merge: false,
+                replace: false,
+            };
+            move_across(from, &target, &Tree::new(from, &options, cancel)?)
+                .await
+        }
+        result => Ok(result?),
+    }
+}
+
 /// Renames a file or directory.
 pub async fn rename_item(
     item: &FSItem,
     new_name: &str,
+) -> Result<(), FsError> {
+    let (options, cancel) = (CopyOptions::default(), CancelToken::new());
+    rename_in(item, new_name, &Tree::new(item.path(), &options, &cancel)?)
+        .await
+}
+
+/// Renames `item`, performing or planning the action according to `tree`.
+pub(crate) async fn rename_in(
+    item: &FSItem,
+    new_name: &str,
+    tree: &Tree<'_>,
 ) -> Result<(), FsError> {
     let mut dst = item.path().to_path_buf();
     dst.set_file_name(new_name);
-    fs::rename(item.path(), &dst).await?;
+    if !tree.planned(|| Action::Rename {
+        src: item.path().clone(),
+        dst: dst.clone(),
+    }) {
+        fs::rename(item.path(), &dst).await?;
+        tree.record(|| Step::Renamed {
+            src: item.path().clone(),
+            dst,
+        });
+    }
     Ok(())
 }
 
@@ -131,17 +1647,53 @@ mod tests {
         file.write_all(txt.as_bytes()).await?;
         let src_item = FSItem::new(&src_file).await;
         // dst dir does not exist => copy should fail
-        assert!(copy_item(&src_item, &dst_file).await.is_err());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Create dst dir
         fs::create_dir(&dst_dir).await?;
         // dst file does not exist, but dst dir does => copy should succeed
-        assert!(copy_item(&src_item, &dst_file).await.is_ok());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_ok()
+        );
         assert!(&dst_file.exists());
         // Modify dst file
         let mut file = File::open(&dst_file).await?;
         file.write_all(b"Huhu baloo").await?;
-        // dst file exists => copy should succeed (overwrite)
-        assert!(copy_item(&src_item, &dst_file).await.is_ok());
+        // dst file exists => copy should fail unless asked to overwrite
+        assert!(matches!(
+            copy_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let overwrite = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        assert!(
+            copy_item(&src_item, &dst_file, &overwrite, &CancelToken::new())
+                .await
+                .is_ok()
+        );
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
         assert_eq!(content, txt);
@@ -165,14 +1717,41 @@ mod tests {
         // Create dst dir
         let dst_dir = tmp_dir.join("d1").join("d2").join("dst");
         fs::create_dir_all(&dst_dir).await?;
-        assert!(copy_item(&src_item, &dst_dir).await.is_ok());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_ok()
+        );
         let dst_file = dst_dir.join(src_item.name());
         assert!(&dst_file.exists());
         // Modify dst file
         let mut file = File::open(&dst_file).await?;
         file.write_all(b"Huhu baloo").await?;
-        // dst file exists => copy should succeed (overwrite)
-        assert!(copy_item(&src_item, &dst_dir).await.is_ok());
+        // dst file exists => copy should fail unless asked to overwrite
+        assert!(matches!(
+            copy_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let overwrite = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        assert!(
+            copy_item(&src_item, &dst_dir, &overwrite, &CancelToken::new())
+                .await
+                .is_ok()
+        );
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
         assert_eq!(content, txt);
@@ -199,7 +1778,16 @@ mod tests {
         fs::symlink(&dst_dir, &tmp_dir.join("dst")).await?;
         let dst_link = tmp_dir.join(dst_dir.file_name().unwrap());
         assert!(&dst_link.exists());
-        assert!(copy_item(&src_item, &dst_link).await.is_ok());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_link,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_ok()
+        );
         let dst_file = dst_link.canonicalize().unwrap().join(src_item.name());
         assert!(&dst_file.exists());
         // Set link to dst file
@@ -209,8 +1797,26 @@ mod tests {
         // Modify dst file
         let mut file = File::open(&dst_file).await?;
         file.write_all(b"Huhu baloo").await?;
-        // dst file exists => copy should succeed (overwrite)
-        assert!(copy_item(&src_item, &dst_link).await.is_ok());
+        // dst file exists => copy should fail unless asked to overwrite
+        assert!(matches!(
+            copy_item(
+                &src_item,
+                &dst_link,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let overwrite = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        assert!(
+            copy_item(&src_item, &dst_link, &overwrite, &CancelToken::new())
+                .await
+                .is_ok()
+        );
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
         assert_eq!(content, txt);
@@ -233,15 +1839,42 @@ mod tests {
         let mut file = File::create(&src_file).await?;
         file.write_all(txt.as_bytes()).await?;
         // parent dir does not exist => copy should fail
-        assert!(copy_item(&src_item, &dst_dir).await.is_err());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Create parent dir
         fs::create_dir(&parent_dir).await?;
         // dst dir does not exist => copy should fail
-        assert!(copy_item(&src_item, &dst_dir).await.is_err());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Create dst dir
         fs::create_dir(&dst_dir).await?;
         // dst dir exists => copy should succeed
-        assert!(copy_item(&src_item, &dst_dir).await.is_ok());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_ok()
+        );
         assert!(&dst_dir.exists());
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
@@ -249,13 +1882,40 @@ mod tests {
         // Modify dst file
         let mut file = File::open(&dst_file).await?;
         file.write_all(b"Huhu baloo").await?;
-        // dst file exists => copy should succeed (overwrite)
-        assert!(copy_item(&src_item, &dst_dir).await.is_ok());
+        // dst file exists => copy should fail unless asked to overwrite
+        assert!(matches!(
+            copy_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let overwrite = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        assert!(
+            copy_item(&src_item, &dst_dir, &overwrite, &CancelToken::new())
+                .await
+                .is_ok()
+        );
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
         assert_eq!(content, txt);
         // Copying a dir to a file should fail
-        assert!(copy_item(&src_item, &dst_file).await.is_err());
+        assert!(
+            copy_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         Ok(())
     }
 
@@ -274,11 +1934,29 @@ mod tests {
         file.write_all(txt.as_bytes()).await?;
         let src_item = FSItem::new(&src_file).await;
         // dst dir does not exist => move should fail
-        assert!(move_item(&src_item, &dst_file).await.is_err());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Create dst dir
         fs::create_dir(&dst_dir).await?;
         // dst file does not exist, but dst dir does => move should succeed
-        assert!(move_item(&src_item, &dst_file).await.is_ok());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_ok()
+        );
         assert!(!&src_file.exists());
         assert!(&dst_file.exists());
         // Recreate src file
@@ -289,8 +1967,26 @@ mod tests {
         // Modify dst file
         let mut file = File::open(&dst_file).await?;
         file.write_all(b"Huhu baloo").await?;
-        // dst file exists => move should succeed (overwrite)
-        assert!(move_item(&src_item, &dst_file).await.is_ok());
+        // dst file exists => move should fail unless asked to overwrite
+        assert!(matches!(
+            move_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let overwrite = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        assert!(
+            move_item(&src_item, &dst_file, &overwrite, &CancelToken::new())
+                .await
+                .is_ok()
+        );
         assert!(!&src_file.exists());
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
@@ -313,12 +2009,26 @@ mod tests {
         file.write_all(txt.as_bytes()).await?;
         let src_item = FSItem::new(&src_file).await;
         // dst dir and its parent do not exist => move should fail
-        assert!(move_item(&src_item, &dst_dir).await.is_err());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Create dst dir
         fs::create_dir_all(&dst_dir).await?;
         // dst file does not exist, but dst dir does => move should succeed
-        move_item(&src_item, &dst_dir).await?;
-        // assert!(move_item(&src_item, &dst_dir).await.is_ok());
+        move_item(
+            &src_item,
+            &dst_dir,
+            &CopyOptions::default(),
+            &CancelToken::new(),
+        )
+        .await?;
         assert!(!&src_file.exists());
         assert!(&dst_file.exists());
         // Recreate src file
@@ -329,8 +2039,26 @@ mod tests {
         // Modify dst file
         let mut file = File::open(&dst_file).await?;
         file.write_all(b"Huhu baloo").await?;
-        // dst file exists => move should succeed (overwrite)
-        assert!(move_item(&src_item, &dst_dir).await.is_ok());
+        // dst file exists => move should fail unless asked to overwrite
+        assert!(matches!(
+            move_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let overwrite = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        assert!(
+            move_item(&src_item, &dst_dir, &overwrite, &CancelToken::new())
+                .await
+                .is_ok()
+        );
         assert!(!&src_file.exists());
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
@@ -358,7 +2086,16 @@ mod tests {
         fs::symlink(&dst_dir, &tmp_dir.join("dst")).await?;
         let dst_link = tmp_dir.join(dst_dir.file_name().unwrap());
         assert!(&dst_link.exists());
-        assert!(move_item(&src_item, &dst_link).await.is_ok());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_link,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_ok()
+        );
         assert!(!&src_file.exists());
         let dst_file = dst_link.canonicalize().unwrap().join(src_item.name());
         assert!(&dst_file.exists());
@@ -374,8 +2111,26 @@ mod tests {
         // Modify dst file
         let mut file = File::open(&dst_file).await?;
         file.write_all(b"Huhu baloo").await?;
-        // dst file exists => move should succeed (overwrite)
-        assert!(move_item(&src_item, &dst_link).await.is_ok());
+        // dst file exists => move should fail unless asked to overwrite
+        assert!(matches!(
+            move_item(
+                &src_item,
+                &dst_link,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let overwrite = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        assert!(
+            move_item(&src_item, &dst_link, &overwrite, &CancelToken::new())
+                .await
+                .is_ok()
+        );
         assert!(&dst_file.exists());
         let content = fs::read_to_string(&dst_file).await?;
         assert_eq!(content, txt);
@@ -398,11 +2153,29 @@ mod tests {
         let mut file = File::create(&src_file).await?;
         file.write_all(txt.as_bytes()).await?;
         // parent dir does not exist => move should fail
-        assert!(move_item(&src_item, &dst_dir).await.is_err());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Create dst dir
         fs::create_dir_all(&dst_dir).await?;
         // dst dir exists => move should succeed
-        assert!(move_item(&src_item, &dst_dir).await.is_ok());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_ok()
+        );
         assert!(!&src_dir.exists());
         assert!(&dst_dir.exists());
         assert!(&dst_file.exists());
@@ -413,12 +2186,39 @@ mod tests {
         let src_item = FSItem::new(&src_dir).await;
         File::create(&src_file).await?;
         // dst dir not empty => move should fail
-        assert!(move_item(&src_item, &dst_dir).await.is_err());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Moving a dir to a file should fail
-        assert!(move_item(&src_item, &dst_file).await.is_err());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_file,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         // Moving a dir into itself should fail
         let src_item = FSItem::new(&parent_dir).await;
-        assert!(move_item(&src_item, &dst_dir).await.is_err());
+        assert!(
+            move_item(
+                &src_item,
+                &dst_dir,
+                &CopyOptions::default(),
+                &CancelToken::new()
+            )
+            .await
+            .is_err()
+        );
         Ok(())
     }
 
@@ -429,7 +2229,7 @@ mod tests {
         let path = tmp_dir.join("to_delete.txt");
         File::create(&path).await?;
         let item = FSItem::new(&path).await;
-        delete_item(&item).await?;
+        delete_item(&item, &CancelToken::new()).await?;
         assert!(!path.exists());
         Ok(())
     }
@@ -443,7 +2243,7 @@ mod tests {
         let file = dir.join("file.txt");
         File::create(&file).await?;
         let item = FSItem::new(&dir).await;
-        delete_item(&item).await?;
+        delete_item(&item, &CancelToken::new()).await?;
         assert!(!dir.exists());
         Ok(())
     }
@@ -483,4 +2283,739 @@ mod tests {
         assert!(expected.exists());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_copy_dir_tree() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let dst_dir = tmp_dir.join("dst");
+        fs::create_dir_all(src_dir.join("a").join("b")).await?;
+        fs::create_dir(&dst_dir).await?;
+        fs::write(src_dir.join("1.txt"), "1").await?;
+        fs::write(src_dir.join("2.txt"), "2").await?;
+        fs::write(src_dir.join("a").join("3.txt"), "3").await?;
+        fs::write(src_dir.join("a").join("b").join("4.txt"), "4").await?;
+        let src_item = FSItem::new(&src_dir).await;
+        copy_item(
+            &src_item,
+            &dst_dir,
+            &CopyOptions::default(),
+            &CancelToken::new(),
+        )
+        .await?;
+        let copy = dst_dir.join("src");
+        assert_eq!(fs::read_to_string(copy.join("1.txt")).await?, "1");
+        assert_eq!(fs::read_to_string(copy.join("2.txt")).await?, "2");
+        assert_eq!(
+            fs::read_to_string(copy.join("a").join("3.txt")).await?,
+            "3"
+        );
+        assert_eq!(
+            fs::read_to_string(copy.join("a").join("b").join("4.txt")).await?,
+            "4"
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_copy_hard_links() -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let dst_dir = tmp_dir.join("dst");
+        fs::create_dir_all(src_dir.join("sub")).await?;
+        fs::create_dir(&dst_dir).await?;
+        fs::write(src_dir.join("a.txt"), "aaa").await?;
+        fs::hard_link(
+            src_dir.join("a.txt"),
+            src_dir.join("sub").join("b.txt"),
+        )
+        .await?;
+        fs::write(src_dir.join("c.txt"), "ccc").await?;
+        let src_item = FSItem::new(&src_dir).await;
+        let report = copy_item(
+            &src_item,
+            &dst_dir,
+            &CopyOptions::default(),
+            &CancelToken::new(),
+        )
+        .await?;
+        assert_eq!(report.files(), 3);
+        assert_eq!(report.hard_linked, 1);
+        let copy = dst_dir.join("src");
+        let a = fs::metadata(copy.join("a.txt")).await?;
+        let b = fs::metadata(copy.join("sub").join("b.txt")).await?;
+        let c = fs::metadata(copy.join("c.txt")).await?;
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(a.nlink(), 2);
+        assert_eq!(c.nlink(), 1);
+        // Replacing existing files keeps the group as well
+        let options = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        let report =
+            copy_item(&src_item, &dst_dir, &options, &CancelToken::new())
+                .await?;
+        assert_eq!(report.hard_linked, 1);
+        let a = fs::metadata(copy.join("a.txt")).await?;
+        let b = fs::metadata(copy.join("sub").join("b.txt")).await?;
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(
+            fs::read_to_string(copy.join("sub").join("b.txt")).await?,
+            "aaa"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_ops() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let src_file = src_dir.join("file.txt");
+        let dst_dir = tmp_dir.join("dst");
+        fs::create_dir(&src_dir).await?;
+        fs::create_dir(&dst_dir).await?;
+        fs::write(&src_file, "Hello world").await?;
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let src_item = FSItem::new(&src_file).await;
+        assert!(matches!(
+            copy_item(&src_item, &dst_dir, &CopyOptions::default(), &cancel)
+                .await,
+            Err(FsError::Cancelled)
+        ));
+        assert!(!dst_dir.join("file.txt").exists());
+        assert!(matches!(
+            move_item(&src_item, &dst_dir, &CopyOptions::default(), &cancel)
+                .await,
+            Err(FsError::Cancelled)
+        ));
+        assert!(src_file.exists());
+        let src_item = FSItem::new(&src_dir).await;
+        assert!(matches!(
+            delete_item(&src_item, &cancel).await,
+            Err(FsError::Cancelled)
+        ));
+        assert!(src_file.exists());
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn test_copy_preserves_metadata()
+    -> Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::dirdiff::{By, DiffItem, DiffItemType};
+
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let sub_dir = src_dir.join("sub");
+        let src_file = sub_dir.join("file.txt");
+        let dst_dir = tmp_dir.join("dst");
+        fs::create_dir_all(&sub_dir).await?;
+        fs::create_dir(&dst_dir).await?;
+        fs::write(&src_file, "Hello world").await?;
+        let has_xattr = xattr::set(&src_file, "user.cocomo", b"test").is_ok();
+        let mtime = FileTime::from_unix_time(1_000_000_000, 0);
+        let atime = FileTime::from_unix_time(1_100_000_000, 0);
+        for path in [&src_file, &sub_dir, &src_dir] {
+            set_file_times(path, atime, mtime)?;
+        }
+        std::fs::set_permissions(
+            &src_file,
+            std::fs::Permissions::from_mode(0o640),
+        )?;
+        std::fs::set_permissions(
+            &sub_dir,
+            std::fs::Permissions::from_mode(0o750),
+        )?;
+        let src_item = FSItem::new(&src_dir).await;
+        copy_item(
+            &src_item,
+            &dst_dir,
+            &CopyOptions::default(),
+            &CancelToken::new(),
+        )
+        .await?;
+        let copy_dir = dst_dir.join("src");
+        let copy_sub_dir = copy_dir.join("sub");
+        let copy_file = copy_sub_dir.join("file.txt");
+        for (path, mode) in [(&copy_file, 0o640), (&copy_sub_dir, 0o750)] {
+            let meta = std::fs::metadata(path)?;
+            assert_eq!(meta.permissions().mode() & 0o7777, mode);
+            assert_eq!(FileTime::from_last_modification_time(&meta), mtime);
+            assert_eq!(FileTime::from_last_access_time(&meta), atime);
+        }
+        if has_xattr {
+            assert_eq!(
+                xattr::get(&copy_file, "user.cocomo")?,
+                Some(b"test".to_vec())
+            );
+        }
+        // The copy compares as same to its original
+        let diff_item = DiffItem::new(
+            &Some(FSItem::new(&src_file).await),
+            &Some(FSItem::new(&copy_file).await),
+        )?;
+        assert_eq!(
+            diff_item.diff_item_type,
+            DiffItemType::Same { by: By::Metadata }
+        );
+        // Without preserving, the copy gets the current time
+        let options = CopyOptions {
+            preserve_times: false,
+            preserve_permissions: false,
+            preserve_ownership: false,
+            preserve_xattrs: false,
+            ..Default::default()
+        };
+        let src_item = FSItem::new(&src_file).await;
+        let plain_copy = tmp_dir.join("plain.txt");
+        copy_item(&src_item, &plain_copy, &options, &CancelToken::new())
+            .await?;
+        let meta = std::fs::metadata(&plain_copy)?;
+        assert_ne!(FileTime::from_last_modification_time(&meta), mtime);
+        if has_xattr {
+            assert_eq!(xattr::get(&plain_copy, "user.cocomo")?, None);
+        }
+        Ok(())
+    }
+
+    /// Creates `src` with a file and links to it, to a file outside of
+    /// `src` and to an absolute path, plus an empty dir `dst/deep`.
+    async fn create_link_tree(tmp_dir: &Path) -> std::io::Result<()> {
+        let src_dir = tmp_dir.join("src");
+        fs::create_dir(&src_dir).await?;
+        fs::create_dir_all(tmp_dir.join("dst").join("deep")).await?;
+        fs::write(tmp_dir.join("outside.txt"), "outside").await?;
+        fs::write(src_dir.join("file.txt"), "inside").await?;
+        fs::symlink("file.txt", src_dir.join("in_link")).await?;
+        fs::symlink("../outside.txt", src_dir.join("out_link")).await?;
+        fs::symlink(tmp_dir.join("outside.txt"), src_dir.join("abs_link"))
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        create_link_tree(tmp_dir).await?;
+        let src_item = FSItem::new(tmp_dir.join("src")).await;
+        let dst_dir = tmp_dir.join("dst").join("deep");
+        let copy = dst_dir.join("src");
+        let cancel = CancelToken::new();
+        for symlinks in [
+            SymlinkPolicy::Preserve,
+            SymlinkPolicy::RewriteRelative,
+            SymlinkPolicy::Dereference,
+            SymlinkPolicy::Skip,
+        ] {
+            let options = CopyOptions {
+                symlinks,
+                ..Default::default()
+            };
+            copy_item(&src_item, &dst_dir, &options, &cancel).await?;
+            assert_eq!(
+                fs::read_to_string(copy.join("file.txt")).await?,
+                "inside"
+            );
+            let in_link = copy.join("in_link");
+            let out_link = copy.join("out_link");
+            let abs_link = copy.join("abs_link");
+            match symlinks {
+                SymlinkPolicy::Preserve => {
+                    assert_eq!(
+                        fs::read_link(&in_link).await?,
+                        Path::new("file.txt")
+                    );
+                    assert_eq!(
+                        fs::read_link(&out_link).await?,
+                        Path::new("../outside.txt")
+                    );
+                    // The link now refers to a non-existing file
+                    assert!(!out_link.exists());
+                }
+                SymlinkPolicy::RewriteRelative => {
+                    assert_eq!(
+                        fs::read_link(&in_link).await?,
+                        Path::new("file.txt")
+                    );
+                    assert_eq!(
+                        fs::read_link(&out_link).await?,
+                        Path::new("../../../outside.txt")
+                    );
+                    assert_eq!(
+                        fs::read_to_string(&out_link).await?,
+                        "outside"
+                    );
+                    assert_eq!(
+                        fs::read_link(&abs_link).await?,
+                        tmp_dir.join("outside.txt")
+                    );
+                }
+                SymlinkPolicy::Dereference => {
+                    for (path, content) in [
+                        (&in_link, "inside"),
+                        (&out_link, "outside"),
+                        (&abs_link, "outside"),
+                    ] {
+                        assert!(!path.is_symlink());
+                        assert_eq!(fs::read_to_string(path).await?, content);
+                    }
+                }
+                SymlinkPolicy::Skip => {
+                    for path in [&in_link, &out_link, &abs_link] {
+                        assert!(fs::symlink_metadata(path).await.is_err());
+                    }
+                }
+            }
+            fs::remove_dir_all(&copy).await?;
+        }
+        // A link given as item is handled the same way
+        let link_item =
+            FSItem::new(tmp_dir.join("src").join("out_link")).await;
+        let options = CopyOptions {
+            symlinks: SymlinkPolicy::RewriteRelative,
+            ..Default::default()
+        };
+        copy_item(&link_item, &dst_dir, &options, &cancel).await?;
+        assert_eq!(
+            fs::read_link(dst_dir.join("out_link")).await?,
+            Path::new("../../outside.txt")
+        );
+        let options = CopyOptions {
+            symlinks: SymlinkPolicy::Dereference,
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        copy_item(&link_item, &dst_dir, &options, &cancel).await?;
+        assert!(!dst_dir.join("out_link").is_symlink());
+        assert_eq!(
+            fs::read_to_string(dst_dir.join("out_link")).await?,
+            "outside"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_broken_symlinks()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let dst_dir = tmp_dir.join("dst");
+        fs::create_dir(&dst_dir).await?;
+        // Broken link
+        let broken_dir = tmp_dir.join("broken");
+        fs::create_dir(&broken_dir).await?;
+        fs::symlink("missing", broken_dir.join("link")).await?;
+        // Links referring to each other
+        let loop_dir = tmp_dir.join("loop");
+        fs::create_dir(&loop_dir).await?;
+        fs::symlink("b", loop_dir.join("a")).await?;
+        fs::symlink("a", loop_dir.join("b")).await?;
+        // Link to an ancestor directory
+        let up_dir = tmp_dir.join("up");
+        fs::create_dir_all(up_dir.join("sub")).await?;
+        fs::symlink("..", up_dir.join("sub").join("link")).await?;
+        let cancel = CancelToken::new();
+        let preserve = CopyOptions::default();
+        let dereference = CopyOptions {
+            symlinks: SymlinkPolicy::Dereference,
+            ..Default::default()
+        };
+        for dir in [&broken_dir, &loop_dir, &up_dir] {
+            let item = FSItem::new(dir).await;
+            copy_item(&item, &dst_dir, &preserve, &cancel).await?;
+        }
+        let copy = dst_dir.join("broken").join("link");
+        assert_eq!(fs::read_link(&copy).await?, Path::new("missing"));
+        let copy = dst_dir.join("loop").join("a");
+        assert_eq!(fs::read_link(&copy).await?, Path::new("b"));
+        let copy = dst_dir.join("up").join("sub").join("link");
+        assert_eq!(fs::read_link(&copy).await?, Path::new(".."));
+        fs::remove_dir_all(&dst_dir).await?;
+        fs::create_dir(&dst_dir).await?;
+        let item = FSItem::new(&broken_dir).await;
+        assert!(matches!(
+            copy_item(&item, &dst_dir, &dereference, &cancel).await,
+            Err(FsError::SourceNotFound(path)) if path.ends_with("link")
+        ));
+        let item = FSItem::new(&loop_dir).await;
+        assert!(matches!(
+            copy_item(&item, &dst_dir, &dereference, &cancel).await,
+            Err(FsError::SymlinkLoop(_))
+        ));
+        let item = FSItem::new(&up_dir).await;
+        assert!(matches!(
+            copy_item(&item, &dst_dir, &dereference, &cancel).await,
+            Err(FsError::SymlinkLoop(path)) if path.ends_with("link")
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_move_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let dst_dir = tmp_dir.join("dst").join("deep");
+        let moved = dst_dir.join("src");
+        let cancel = CancelToken::new();
+        for symlinks in [
+            SymlinkPolicy::Preserve,
+            SymlinkPolicy::RewriteRelative,
+            SymlinkPolicy::Dereference,
+            SymlinkPolicy::Skip,
+        ] {
+            create_link_tree(tmp_dir).await?;
+            let options = CopyOptions {
+                symlinks,
+                ..Default::default()
+            };
+            let src_item = FSItem::new(&src_dir).await;
+            move_item(&src_item, &dst_dir, &options, &cancel).await?;
+            assert_eq!(
+                fs::read_to_string(moved.join("file.txt")).await?,
+                "inside"
+            );
+            assert!(!src_dir.join("file.txt").exists());
+            let out_link = moved.join("out_link");
+            match symlinks {
+                SymlinkPolicy::Preserve => {
+                    assert!(!src_dir.exists());
+                    assert_eq!(
+                        fs::read_link(&out_link).await?,
+                        Path::new("../outside.txt")
+                    );
+                }
+                SymlinkPolicy::RewriteRelative => {
+                    assert!(!src_dir.exists());
+                    assert_eq!(
+                        fs::read_link(&out_link).await?,
+                        Path::new("../../../outside.txt")
+                    );
+                    assert_eq!(
+                        fs::read_link(moved.join("in_link")).await?,
+                        Path::new("file.txt")
+                    );
+                }
+                SymlinkPolicy::Dereference => {
+                    assert!(!src_dir.exists());
+                    assert!(!out_link.is_symlink());
+                    assert_eq!(
+                        fs::read_to_string(&out_link).await?,
+                        "outside"
+                    );
+                    assert_eq!(
+                        fs::read_to_string(moved.join("in_link")).await?,
+                        "inside"
+                    );
+                }
+                SymlinkPolicy::Skip => {
+                    // Links are left behind
+                    assert!(src_dir.join("out_link").is_symlink());
+                    assert!(fs::symlink_metadata(&out_link).await.is_err());
+                }
+            }
+            fs::remove_dir_all(tmp_dir).await?;
+            fs::create_dir(tmp_dir).await?;
+        }
+        Ok(())
+    }
+
+    // Moving to another file system can't be arranged in a test, so the
+    // fallback is exercised directly.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_move_across() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        create_link_tree(tmp_dir).await?;
+        let src_dir = tmp_dir.join("src");
+        let moved = tmp_dir.join("dst").join("deep").join("src");
+        let (options, cancel) = (CopyOptions::default(), CancelToken::new());
+        let target = Target {
+            path: moved.clone(),
+            merge: false,
+            replace: false,
+        };
+        let tree = Tree::new(&src_dir, &options, &cancel)?;
+        move_across(&src_dir, &target, &tree).await?;
+        assert!(!src_dir.exists());
+        assert_eq!(
+            fs::read_to_string(moved.join("file.txt")).await?,
+            "inside"
+        );
+        assert_eq!(
+            fs::read_link(moved.join("out_link")).await?,
+            Path::new("../outside.txt")
+        );
+        // The copy is removed if the tree can't be copied completely
+        fs::rename(&moved, &src_dir).await?;
+        let _socket =
+            std::os::unix::net::UnixListener::bind(src_dir.join("socket"))?;
+        let tree = Tree::new(&src_dir, &options, &cancel)?;
+        assert!(matches!(
+            move_across(&src_dir, &target, &tree).await,
+            Err(FsError::Unsupported(_))
+        ));
+        assert!(fs::symlink_metadata(&moved).await.is_err());
+        assert!(src_dir.join("file.txt").exists());
+        assert!(src_dir.join("in_link").is_symlink());
+        // Copies are verified
+        fs::write(tmp_dir.join("copy.txt"), "inside").await?;
+        verify_copy(
+            &src_dir.join("file.txt"),
+            &tmp_dir.join("copy.txt"),
+            &cancel,
+        )
+        .await?;
+        fs::write(tmp_dir.join("copy.txt"), "insidf").await?;
+        assert!(
+            verify_copy(
+                &src_dir.join("file.txt"),
+                &tmp_dir.join("copy.txt"),
+                &cancel
+            )
+            .await
+            .is_err()
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_move_across_replace()
+    -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_file = tmp_dir.join("src.txt");
+        let dst_file = tmp_dir.join("dst.txt");
+        fs::write(&src_file, "new").await?;
+        fs::write(&dst_file, "old").await?;
+        let options = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        let cancel = CancelToken::new();
+        let names = || async {
+            let mut names = Vec::new();
+            let mut entries = fs::read_dir(tmp_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                names.push(entry.file_name());
+            }
+            names.sort();
+            io::Result::Ok(names)
+        };
+        // Files of the proc file system are on another device and report a
+        // size of 0, so their copies never pass the verification
+        #[cfg(target_os = "linux")]
+        {
+            let proc_file = FSItem::new("/proc/self/status").await;
+            assert!(matches!(
+                move_item(&proc_file, &dst_file, &options, &cancel).await,
+                Err(FsError::VerificationFailed(_))
+            ));
+            assert_eq!(fs::read_to_string(&dst_file).await?, "old");
+            assert_eq!(names().await?, ["dst.txt", "src.txt"]);
+        }
+        // Once verified, the copy replaces the file
+        let target = Target {
+            path: dst_file.clone(),
+            merge: false,
+            replace: true,
+        };
+        let tree = Tree::new(&src_file, &options, &cancel)?;
+        move_across(&src_file, &target, &tree).await?;
+        assert_eq!(fs::read_to_string(&dst_file).await?, "new");
+        assert!(!src_file.exists());
+        assert_eq!(names().await?, ["dst.txt"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verified_copy() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_file = tmp_dir.join("big.bin");
+        let content: Vec<u8> = (0..COPY_BUF_SIZE * 2 + 17)
+            .map(|i| (i % 253) as u8)
+            .collect();
+        fs::write(&src_file, &content).await?;
+        let options = CopyOptions {
+            verify: true,
+            ..Default::default()
+        };
+        let dst_file = tmp_dir.join("copy.bin");
+        let src_item = FSItem::new(&src_file).await;
+        let report =
+            copy_item(&src_item, &dst_file, &options, &CancelToken::new())
+                .await?;
+        assert_eq!(fs::read(&dst_file).await?, content);
+        assert_eq!(report.files(), 1);
+        assert!(report.strategy().is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_atomic_replace() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_file = tmp_dir.join("src.txt");
+        let dst_dir = tmp_dir.join("dst");
+        let dst_file = dst_dir.join("src.txt");
+        fs::create_dir(&dst_dir).await?;
+        fs::write(&src_file, "new").await?;
+        fs::write(&dst_file, "old").await?;
+        let names = async || -> std::io::Result<Vec<OsString>> {
+            let mut names = Vec::new();
+            let mut entries = fs::read_dir(&dst_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                names.push(entry.file_name());
+            }
+            Ok(names)
+        };
+        // An interrupted copy leaves the existing file untouched
+        let options = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        let cancel = CancelToken::new();
+        let tree = Tree::new(&src_file, &options, &cancel)?;
+        cancel.cancel();
+        let metadata = fs::metadata(&src_file).await?;
+        assert!(matches!(
+            copy_file(&src_file, &dst_file, true, metadata, &tree).await,
+            Err(FsError::Cancelled)
+        ));
+        assert_eq!(fs::read_to_string(&dst_file).await?, "old");
+        assert_eq!(names().await?, vec![OsString::from("src.txt")]);
+        let src_item = FSItem::new(&src_file).await;
+        copy_item(&src_item, &dst_dir, &options, &CancelToken::new()).await?;
+        assert_eq!(fs::read_to_string(&dst_file).await?, "new");
+        assert_eq!(names().await?, vec![OsString::from("src.txt")]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_conflict_policies() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let dst_dir = tmp_dir.join("dst");
+        let copy = dst_dir.join("src");
+        fs::create_dir(&src_dir).await?;
+        fs::create_dir_all(&copy).await?;
+        fs::write(src_dir.join("a.txt"), "new").await?;
+        fs::write(src_dir.join("b.txt"), "b").await?;
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        let newer = FileTime::from_unix_time(2_000_000_000, 0);
+        let reset = async |content: &str, mtime: FileTime| {
+            let _ = fs::remove_dir_all(&copy).await;
+            fs::create_dir(&copy).await?;
+            fs::write(copy.join("a.txt"), content).await?;
+            set_file_times(copy.join("a.txt"), mtime, mtime)
+        };
+        let src_item = FSItem::new(&src_dir).await;
+        let cancel = CancelToken::new();
+        let options = |on_conflict| CopyOptions {
+            on_conflict,
+            ..Default::default()
+        };
+        // Ask => nothing copied
+        reset("old", old).await?;
+        assert!(matches!(
+            copy_item(&src_item, &dst_dir, &options(ConflictPolicy::Ask), &cancel)
+                .await,
+            Err(FsError::DestinationAlreadyExists(path))
+                if path == copy.join("a.txt")
+        ));
+        assert!(!copy.join("b.txt").exists());
+        // A conflict showing up after the check isn't reported as such
+        let ask = options(ConflictPolicy::Ask);
+        let tree = Tree::new(&src_dir, &ask, &cancel)?;
+        assert!(matches!(
+            copy_entry(&src_dir, &copy, &tree, &mut Vec::new()).await,
+            Err(FsError::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists
+        ));
+        // Skip
+        copy_item(
+            &src_item,
+            &dst_dir,
+            &options(ConflictPolicy::Skip),
+            &cancel,
+        )
+        .await?;
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "old");
+        assert_eq!(fs::read_to_string(copy.join("b.txt")).await?, "b");
+        // Overwrite if older
+        let if_older = options(ConflictPolicy::OverwriteIfOlder);
+        copy_item(&src_item, &dst_dir, &if_older, &cancel).await?;
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "new");
+        reset("newer", newer).await?;
+        copy_item(&src_item, &dst_dir, &if_older, &cancel).await?;
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "newer");
+        // Keep both
+        let keep_both = options(ConflictPolicy::KeepBoth);
+        copy_item(&src_item, &dst_dir, &keep_both, &cancel).await?;
+        copy_item(&src_item, &dst_dir, &keep_both, &cancel).await?;
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "newer");
+        assert_eq!(fs::read_to_string(copy.join("a (1).txt")).await?, "new");
+        assert_eq!(fs::read_to_string(copy.join("a (2).txt")).await?, "new");
+        assert_eq!(fs::read_to_string(copy.join("b (1).txt")).await?, "b");
+        // Overwrite
+        reset("old", old).await?;
+        copy_item(
+            &src_item,
+            &dst_dir,
+            &options(ConflictPolicy::Overwrite),
+            &cancel,
+        )
+        .await?;
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "new");
+        // Moving with skip leaves conflicting items behind
+        reset("old", old).await?;
+        move_item(
+            &src_item,
+            &dst_dir,
+            &options(ConflictPolicy::Skip),
+            &cancel,
+        )
+        .await?;
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "old");
+        assert_eq!(fs::read_to_string(copy.join("b.txt")).await?, "b");
+        assert!(src_dir.join("a.txt").exists());
+        assert!(!src_dir.join("b.txt").exists());
+        // A directory in the way is replaced only if confirmed
+        fs::write(src_dir.join("b.txt"), "b").await?;
+        reset("old", old).await?;
+        fs::create_dir(copy.join("b.txt")).await?;
+        fs::write(copy.join("b.txt").join("c.txt"), "c").await?;
+        let overwrite = options(ConflictPolicy::Overwrite);
+        assert!(matches!(
+            copy_item(&src_item, &dst_dir, &overwrite, &cancel).await,
+            Err(FsError::DirectoryInTheWay(path)) if path == copy.join("b.txt")
+        ));
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "old");
+        copy_item(&src_item, &dst_dir, &if_older, &cancel).await?;
+        assert_eq!(fs::read_to_string(copy.join("a.txt")).await?, "new");
+        assert!(copy.join("b.txt").join("c.txt").exists());
+        let replace_dirs = CopyOptions {
+            replace_dirs: true,
+            ..overwrite
+        };
+        copy_item(&src_item, &dst_dir, &replace_dirs, &cancel).await?;
+        assert_eq!(fs::read_to_string(copy.join("b.txt")).await?, "b");
+        Ok(())
+    }
 }
@@ -0,0 +1,564 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Journal Module (`journal`)
+//!
+//! This module provides the [`Journal`], which records the file system
+//! operations performed in a session, so that they can be undone and redone.
+//!
+//! Instead of being deleted, items removed or replaced by a journaled
+//! operation are moved to a backup directory, from which they are restored
+//! when the operation is undone. The backups of an operation are deleted
+//! permanently once it drops out of the journal.
+//!
+//! Backups are kept on the file system of the items, so that they can be
+//! moved there by renaming: in the backup directory of the journal, if it
+//! is on the same file system, otherwise in `$topdir/.cocomo-$uid` at the
+//! top of the mount point, like trash directories. Items on file systems
+//! where no backup can be kept are deleted without backup; they are
+//! reported by [`Journal::unrecoverable`].
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use tokio::fs;
+
+use crate::{
+    cancel::CancelToken,
+    fsitem::FSItem,
+    fsops::{
+        CopyOptions, FsError, Tree, copy_in, delete_in, device, move_in,
+        rename_in, transfer, trash_in,
+    },
+    plan::Operation,
+    trash,
+};
+
+/// A single change made by a journaled operation.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Step {
+    /// The file or link `path` has been created.
+    Created(PathBuf),
+    /// The directory `path` has been created.
+    CreatedDir(PathBuf),
+    /// `src` has been renamed (moved) to `dst`.
+    Renamed { src: PathBuf, dst: PathBuf },
+    /// The item `path` has been removed, keeping it at `backup`.
+    Removed { path: PathBuf, backup: PathBuf },
+    /// The file `path` has been saved to `backup` before being replaced.
+    Saved { path: PathBuf, backup: PathBuf },
+    /// The item `path` has been moved to the trash, ending up at `trashed`.
+    Trashed { path: PathBuf, trashed: PathBuf },
+}
+
+/// Collects the steps of a journaled operation.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    /// Directory holding the backups of the operation in the backup
+    /// directory of the journal.
+    backup_dir: PathBuf,
+    /// Path of the directory holding the backups of the operation on other
+    /// file systems, relative to their backup root.
+    name: PathBuf,
+    /// The directories holding the backups of the operation by device, or
+    /// `None` for devices no backup can be kept on.
+    backup_dirs: Mutex<HashMap<u64, Option<PathBuf>>>,
+    steps: Mutex<Vec<Step>>,
+    /// Items removed or replaced without backup.
+    unrecoverable: Mutex<Vec<PathBuf>>,
+}
+
+impl Recorder {
+    /// Creates a new `Recorder` keeping backups in `backup_dir`, or in
+    /// `name` below the backup root of other file systems.
+    pub(crate) fn new(backup_dir: PathBuf, name: PathBuf) -> Self {
+        Self {
+            backup_dir,
+            name,
+            backup_dirs: Mutex::new(HashMap::new()),
+            steps: Mutex::new(Vec::new()),
+            unrecoverable: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns an unused path in the backup directory on the file system of
+    /// the item at `path`, creating the directory if necessary, or `None` if
+    /// no backup can be kept there.
+    pub(crate) async fn backup_path(&self, path: &Path) -> Option<PathBuf> {
+        let dev = device(&fs::symlink_metadata(path).await.ok()?);
+        let known = self.backup_dirs.lock().unwrap().get(&dev).cloned();
+        let backup_dir = match known {
+            Some(backup_dir) => backup_dir,
+            None => {
+                let backup_dir = self.create_backup_dir(path, dev).await.ok();
+                self.backup_dirs
+                    .lock()
+                    .unwrap()
+                    .insert(dev, backup_dir.clone());
+                backup_dir
+            }
+        }?;
+        let n = self.steps.lock().unwrap().len();
+        Some(backup_dir.join(n.to_string()))
+    }
+
+    /// Creates the directory holding the backups of the operation for the
+    /// item at `path` on the device `dev`, as described in the module
+    /// documentation, and returns its path.
+    async fn create_backup_dir(
+        &self,
+        path: &Path,
+        dev: u64,
+    ) -> io::Result<PathBuf> {
+        if fs::create_dir_all(&self.backup_dir).await.is_ok()
+            && device(&fs::metadata(&self.backup_dir).await?) == dev
+        {
+            return Ok(self.backup_dir.clone());
+        }
+        let root = trash::top_dir(path, dev)
+            .await?
+            .join(format!(".cocomo-{}", trash::uid()));
+        trash::create_private_dir(&root).await?;
+        let backup_dir = root.join(&self.name);
+        fs::create_dir_all(&backup_dir).await?;
+        Ok(backup_dir)
+    }
+
+    /// Records `step`.
+    pub(crate) fn record(&self, step: Step) {
+        self.steps.lock().unwrap().push(step);
+    }
+
+    /// Records that the item at `path` has been removed or replaced without
+    /// backup, unless this has been recorded for one of its ancestors.
+    pub(crate) fn record_unrecoverable(&self, path: &Path) {
+        let mut unrecoverable = self.unrecoverable.lock().unwrap();
+        if !unrecoverable.iter().any(|item| path.starts_with(item)) {
+            unrecoverable.push(path.into());
+        }
+    }
+
+    /// Returns the directories the backups of the operation may have been
+    /// kept in.
+    fn backup_dirs(&self) -> Vec<PathBuf> {
+        let backup_dirs = self.backup_dirs.lock().unwrap();
+        let other_dirs = backup_dirs
+            .values()
+            .flatten()
+            .filter(|&dir| dir != &self.backup_dir)
+            .cloned();
+        std::iter::once(self.backup_dir.clone())
+            .chain(other_dirs)
+            .collect()
+    }
+
+    /// Returns the recorded steps.
+    pub(crate) fn into_steps(self) -> Vec<Step> {
+        self.steps.into_inner().unwrap()
+    }
+}
+
+/// An operation recorded in the journal.
+#[derive(Debug)]
+struct Entry {
+    /// The item the operation has been applied to.
+    item: FSItem,
+    operation: Operation,
+    options: CopyOptions,
+    /// Directories holding the backups of the operation.
+    backup_dirs: Vec<PathBuf>,
+    /// The changes made by the operation, in order.
+    steps: Vec<Step>,
+}
+
+#[derive(Debug)]
+struct State {
+    backup_dir: PathBuf,
+    /// Directories holding the backups of this journal on other file
+    /// systems.
+    other_backup_dirs: Vec<PathBuf>,
+    /// Items removed or replaced without backup by the last operation
+    /// applied or redone.
+    unrecoverable: Vec<PathBuf>,
+    /// Maximum number of operations which can be undone.
+    limit: usize,
+    /// Operations which can be undone, the last one at the end.
+    done: Vec<Entry>,
+    /// Operations which can be redone, the last one undone at the end.
+    undone: Vec<Entry>,
+    /// Number of operations recorded so far.
+    count: u64,
+}
+
+/// Journal of file system operations, allowing to undo the last operations
+/// and to redo them.
+///
+/// Clones of a `Journal` share the same records.
+#[derive(Clone, Debug)]
+pub struct Journal {
+    state: Arc<Mutex<State>>,
+}
+
+impl Journal {
+    /// Creates a new, empty `Journal` keeping the last `limit` operations,
+    /// with the backups of removed or replaced items stored in
+    /// `backup_dir`.
+    pub fn new(backup_dir: impl Into<PathBuf>, limit: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                backup_dir: backup_dir.into(),
+                other_backup_dirs: Vec::new(),
+                unrecoverable: Vec::new(),
+                limit,
+                done: Vec::new(),
+                undone: Vec::new(),
+                count: 0,
+            })),
+        }
+    }
+
+    /// Returns `true` if there is an operation to be undone.
+    pub fn can_undo(&self) -> bool {
+        !self.state.lock().unwrap().done.is_empty()
+    }
+
+    /// Returns `true` if there is an operation to be redone.
+    pub fn can_redo(&self) -> bool {
+        !self.state.lock().unwrap().undone.is_empty()
+    }
+
+    /// Returns the items removed or replaced by the last operation applied
+    /// or redone without being backed up, as no backup could be kept on
+    /// their file system. Undoing the operation can't restore them.
+    pub fn unrecoverable(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().unrecoverable.clone()
+    }
+
+    /// Applies `operation` to `item`, using `options` like the functions in
+    /// [`fsops`](crate::fsops) do, and records it in the journal.
+    ///
+    /// Operations failing or cancelled halfway are recorded as well, so that
+    /// the changes made so far can be undone. Operations undone before can
+    /// no longer be redone afterwards.
+    pub async fn apply(
+        &self,
+        item: &FSItem,
+        operation: &Operation,
+        options: &CopyOptions,
+        cancel: &CancelToken,
+    ) -> Result<(), FsError> {
+        let result = self.run(item, operation, options, cancel).await;
+        self.state.lock().unwrap().undone.clear();
+        result
+    }
+
+    /// Applies `operation` to `item` and records it, dropping the oldest
+    /// records exceeding the limit of the journal.
+    async fn run(
+        &self,
+        item: &FSItem,
+        operation: &Operation,
+        options: &CopyOptions,
+        cancel: &CancelToken,
+    ) -> Result<(), FsError> {
+        let recorder = {
+            let mut state = self.state.lock().unwrap();
+            state.count += 1;
+            let count = state.count.to_string();
+            let name = Path::new(
+                state.backup_dir.file_name().unwrap_or("backup".as_ref()),
+            )
+            .join(&count);
+            Recorder::new(state.backup_dir.join(count), name)
+        };
+        let tree =
+            Tree::new(item.path(), options, cancel)?.journaling(&recorder);
+        let result = match operation {
+            Operation::Copy { to } => copy_in(item, to, &tree).await,
+            Operation::Move { to } => move_in(item, to, &tree).await,
+            Operation::Delete => delete_in(item, &tree).await,
+            Operation::Trash => trash_in(item, &tree).await,
+            Operation::Rename { new_name } => {
+                rename_in(item, new_name, &tree).await
+            }
+        };
+        drop(tree);
+        let backup_dirs = recorder.backup_dirs();
+        let unrecoverable = recorder.unrecoverable.lock().unwrap().clone();
+        let steps = recorder.into_steps();
+        {
+            let mut state = self.state.lock().unwrap();
+            state.unrecoverable = unrecoverable;
+            for dir in &backup_dirs[1..] {
+                // The directory of this journal below the backup root
+                if let Some(parent) = dir.parent()
+                    && !state.other_backup_dirs.iter().any(|d| d == parent)
+                {
+                    state.other_backup_dirs.push(parent.into());
+                }
+            }
+        }
+        if steps.is_empty() {
+            for dir in &backup_dirs {
+                let _ = fs::remove_dir(dir).await;
+            }
+            return result;
+        }
+        let dropped = {
+            let mut state = self.state.lock().unwrap();
+            state.done.push(Entry {
+                item: item.clone(),
+                operation: operation.clone(),
+                options: options.clone(),
+                backup_dirs,
+                steps,
+            });
+            let excess = state.done.len().saturating_sub(state.limit);
+            state.done.drain(..excess).collect::<Vec<_>>()
+        };
+        for entry in dropped {
+            for dir in &entry.backup_dirs {
+                // Backups which can't be removed are left to the user
+                let _ = fs::remove_dir_all(dir).await;
+            }
+        }
+        result
+    }
+
+    /// Undoes the last operation recorded and returns `true`, or returns
+    /// `false` if there is none.
+    ///
+    /// If undoing fails or gets cancelled, the changes not reverted so far
+    /// stay in the journal, so that undoing can be retried.
+    pub async fn undo(&self, cancel: &CancelToken) -> Result<bool, FsError> {
+        let Some(mut entry) = self.state.lock().unwrap().done.pop() else {
+            return Ok(false);
+        };
+        while let Some(step) = entry.steps.last() {
+            if let Err(err) = revert(step, cancel).await {
+                self.state.lock().unwrap().done.push(entry);
+                return Err(err);
+            }
+            entry.steps.pop();
+        }
+        // All backups have been restored
+        for dir in &entry.backup_dirs {
+            let _ = fs::remove_dir(dir).await;
+        }
+        self.state.lock().unwrap().undone.push(entry);
+        Ok(true)
+    }
+
+    /// Redoes the last operation undone and returns `true`, or returns
+    /// `false` if there is none.
+    pub async fn redo(&self, cancel: &CancelToken) -> Result<bool, FsError> {
+        let Some(entry) = self.state.lock().unwrap().undone.pop() else {
+            return Ok(false);
+        };
+        self.run(&entry.item, &entry.operation, &entry.options, cancel)
+            .await?;
+        Ok(true)
+    }
+
+    /// Forgets all recorded operations and permanently deletes the backups.
+    pub async fn clear(&self) -> io::Result<()> {
+        let (backup_dir, other_backup_dirs) = {
+            let mut state = self.state.lock().unwrap();
+            state.done.clear();
+            state.undone.clear();
+            (
+                state.backup_dir.clone(),
+                std::mem::take(&mut state.other_backup_dirs),
+            )
+        };
+        for dir in other_backup_dirs {
+            // Backups which can't be removed are left to the user, the
+            // backup root to other sessions still using it
+            let _ = fs::remove_dir_all(&dir).await;
+            if let Some(root) = dir.parent() {
+                let _ = fs::remove_dir(root).await;
+            }
+        }
+        match fs::remove_dir_all(&backup_dir).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reverts `step`.
+async fn revert(step: &Step, cancel: &CancelToken) -> Result<(), FsError> {
+    if cancel.is_cancelled() {
+        return Err(FsError::Cancelled);
+    }
+    let result = match step {
+        Step::Created(path) => fs::remove_file(path).await,
+        Step::CreatedDir(path) => fs::remove_dir(path).await,
+        Step::Renamed { src, dst } => {
+            ensure_free(src).await?;
+            fs::rename(dst, src).await
+        }
+        Step::Removed { path, backup } => {
+            ensure_free(path).await?;
+            return transfer(backup, path, cancel).await;
+        }
+        Step::Saved { path, backup } => {
+            // Atomically replaces the file, if it is still there
+            match fs::rename(backup, path).await {
+                Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                    let _ = fs::remove_file(path).await;
+                    return transfer(backup, path, cancel).await;
+                }
+                result => result,
+            }
+        }
+        Step::Trashed { path, trashed } => {
+            ensure_free(path).await?;
+            trash::restore(trashed, path).await
+        }
+    };
+    match result {
+        // Already gone, e.g. removed by the user in the meantime
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// Returns [`FsError::DestinationAlreadyExists`] if there is an item at
+/// `path`.
+async fn ensure_free(path: &Path) -> Result<(), FsError> {
+    if fs::symlink_metadata(path).await.is_ok() {
+        Err(FsError::DestinationAlreadyExists(path.into()))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::fsops::ConflictPolicy;
+
+    #[tokio::test]
+    async fn test_undo_redo() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let dst_dir = tmp_dir.join("dst");
+        fs::create_dir_all(src_dir.join("sub")).await?;
+        fs::create_dir_all(dst_dir.join("src")).await?;
+        fs::write(src_dir.join("a.txt"), "new").await?;
+        fs::write(src_dir.join("sub").join("b.txt"), "bb").await?;
+        fs::write(dst_dir.join("src").join("a.txt"), "old").await?;
+        let journal = Journal::new(tmp_dir.join("backup"), 2);
+        let cancel = CancelToken::new();
+        let options = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        let src_item = FSItem::new(&src_dir).await;
+        let copy = Operation::Copy {
+            to: dst_dir.clone(),
+        };
+        assert!(!journal.can_undo());
+        journal.apply(&src_item, &copy, &options, &cancel).await?;
+        let target = dst_dir.join("src");
+        assert_eq!(fs::read_to_string(target.join("a.txt")).await?, "new");
+        assert!(target.join("sub").join("b.txt").exists());
+        // Undoing restores the overwritten file and removes the copies
+        assert!(journal.undo(&cancel).await?);
+        assert_eq!(fs::read_to_string(target.join("a.txt")).await?, "old");
+        assert!(!target.join("sub").exists());
+        assert!(journal.can_redo());
+        assert!(journal.redo(&cancel).await?);
+        assert_eq!(fs::read_to_string(target.join("a.txt")).await?, "new");
+        // Deleted items are restored
+        let sub_item = FSItem::new(&src_dir.join("sub")).await;
+        journal
+            .apply(&sub_item, &Operation::Delete, &options, &cancel)
+            .await?;
+        assert!(!src_dir.join("sub").exists());
+        let a_item = FSItem::new(&src_dir.join("a.txt")).await;
+        let rename = Operation::Rename {
+            new_name: "c.txt".into(),
+        };
+        journal.apply(&a_item, &rename, &options, &cancel).await?;
+        assert!(src_dir.join("c.txt").exists());
+        assert!(journal.undo(&cancel).await?);
+        assert!(journal.undo(&cancel).await?);
+        assert_eq!(
+            fs::read_to_string(src_dir.join("sub").join("b.txt")).await?,
+            "bb"
+        );
+        assert!(src_dir.join("a.txt").exists());
+        assert!(!src_dir.join("c.txt").exists());
+        // Only the last two operations are kept
+        assert!(!journal.undo(&cancel).await?);
+        assert_eq!(fs::read_to_string(target.join("a.txt")).await?, "new");
+        // A new operation discards the undone ones
+        journal
+            .apply(
+                &src_item,
+                &Operation::Move {
+                    to: tmp_dir.join("moved"),
+                },
+                &options,
+                &cancel,
+            )
+            .await?;
+        assert!(!journal.can_redo());
+        assert!(!src_dir.exists());
+        assert!(journal.undo(&cancel).await?);
+        assert!(src_dir.join("a.txt").exists());
+        assert!(!tmp_dir.join("moved").exists());
+        // Items created in the way of undoing are not overwritten
+        journal
+            .apply(&a_item, &Operation::Delete, &options, &cancel)
+            .await?;
+        fs::write(src_dir.join("a.txt"), "other").await?;
+        assert!(matches!(
+            journal.undo(&cancel).await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        fs::remove_file(src_dir.join("a.txt")).await?;
+        assert!(journal.undo(&cancel).await?);
+        assert_eq!(fs::read_to_string(src_dir.join("a.txt")).await?, "new");
+        journal.clear().await?;
+        assert!(!journal.can_redo());
+        assert!(!tmp_dir.join("backup").exists());
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_backup_path() -> io::Result<()> {
+        let tmp = tempdir()?;
+        let file = tmp.path().join("a.txt");
+        fs::write(&file, "a").await?;
+        let backup_dir = tmp.path().join("backup").join("1");
+        let recorder =
+            Recorder::new(backup_dir.clone(), PathBuf::from("backup/1"));
+        // Items on the file system of the journal are backed up there
+        assert_eq!(
+            recorder.backup_path(&file).await,
+            Some(backup_dir.join("0"))
+        );
+        assert_eq!(recorder.backup_dirs(), [backup_dir]);
+        assert_eq!(recorder.backup_path(&tmp.path().join("x")).await, None);
+        // Items below an unrecoverable directory are not listed
+        recorder.record_unrecoverable(tmp.path());
+        recorder.record_unrecoverable(&file);
+        assert_eq!(*recorder.unrecoverable.lock().unwrap(), [tmp.path()]);
+        Ok(())
+    }
+}
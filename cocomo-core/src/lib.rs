@@ -15,13 +15,41 @@
 //! comparison tool. It includes types for representing file system items,
 //! reading directory contents, and computing differences between directories.
 
+mod attrs;
+mod cancel;
+mod content;
+mod copyfile;
 pub mod dirdiff;
+mod encoding;
 mod fsitem;
 pub mod fsops;
+pub mod journal;
+pub mod patch;
+pub mod plan;
 mod readdir;
+mod renames;
+mod scan;
 pub mod textdiff;
+mod trash;
 
-pub use dirdiff::{By, DiffItem, DiffItemType, DiffSide, DirDiff};
+pub use attrs::AttrDiff;
+pub use cancel::CancelToken;
+pub use dirdiff::{
+    By, DiffItem, DiffItemType, DiffOptions, DiffSide, DirDiff, LinkMode,
+};
+pub use encoding::TextEncoding;
+pub use encoding_rs::Encoding;
 pub use fsitem::{FSItem, FSItemType};
-pub use fsops::{FsError, copy_item, delete_item, move_item, rename_item};
-pub use textdiff::{DiffLine, LineDiffType, TextDiff};
+pub use fsops::{
+    ConflictPolicy, CopyOptions, CopyReport, CopyStrategy, FsError,
+    SymlinkPolicy, copy_item, delete_item, move_item, relative_path,
+    rename_item, trash_item,
+};
+pub use journal::Journal;
+pub use patch::{DEFAULT_CONTEXT, patch, unified_diff};
+pub use plan::{Action, Operation, Plan, plan};
+pub use readdir::ItemFilter;
+pub use scan::{ScanEvent, ScanEventSender};
+pub use textdiff::{
+    DiffLine, LineDiffType, TextDiff, TextDiffOptions, WhitespaceMode,
+};
@@ -0,0 +1,534 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Patch Module (`patch`)
+//!
+//! This module renders the comparison of two text files in the unified diff
+//! format, and the comparison of two directories as a patch holding the
+//! unified diffs of all differing, new and deleted files, which can be
+//! applied with `patch -p1` or `git apply`.
+//!
+//! Files in directory patches are labelled by their paths relative to the
+//! compared directories, prefixed by `a/` on the left side and `b/` on the
+//! right side; missing files by `/dev/null`. Binary files are only reported
+//! as differing, symbolic links and special files are left out.
+//!
+//! The patch is encoded in UTF-8. Text files in other encodings, with a
+//! byte order mark or holding invalid sequences can't be represented
+//! verbatim, so they are only reported as differing, too.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use encoding_rs::UTF_8;
+use tokio::io;
+
+use crate::{
+    DiffOptions, DiffSide, FSItem, LineDiffType, TextDiff, TextDiffOptions,
+    dirdiff::{DiffItem, DiffItemType, DirDiff},
+};
+
+/// The number of context lines used by default.
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// The label of a missing file.
+const NO_FILE: &str = "/dev/null";
+
+/// The timestamp of a missing file.
+const NO_TIME: &str = "1970-01-01 00:00:00.000000000 +0000";
+
+/// A line of a unified diff.
+struct PatchLine<'a> {
+    /// ' ' for context lines, '-' for removed and '+' for added lines.
+    tag: char,
+    /// The content of the line, including its terminator.
+    content: &'a str,
+    /// The line differs, but the difference is ignored by the comparison
+    /// options; it is shown only as part of a hunk of other differences.
+    ignored: bool,
+}
+
+/// Returns the unified diff of the files compared in `diff`, showing
+/// `context` unchanged lines around the differences and labelling the files
+/// by their paths. Returns an empty string if the files don't differ.
+///
+/// Files whose text isn't their content encoded in UTF-8 are only reported
+/// as differing, as described in the module documentation.
+pub fn unified_diff(diff: &TextDiff, context: usize) -> String {
+    let label = |file: &FSItem| {
+        (!file.name().is_empty()).then(|| file.path().display().to_string())
+    };
+    let mut patch = String::new();
+    write_unified(
+        &mut patch,
+        diff,
+        label(&diff.left_file).as_deref(),
+        label(&diff.right_file).as_deref(),
+        context,
+    );
+    patch
+}
+
+/// Returns a patch turning the left directory compared in `diff` into the
+/// right one, as described in the module documentation.
+///
+/// The files are compared according to `options`, showing `context`
+/// unchanged lines around the differences. Subdirectories not compared yet
+/// are compared on the fly.
+pub async fn patch(
+    diff: &DirDiff,
+    context: usize,
+    options: &TextDiffOptions,
+) -> io::Result<String> {
+    let roots = Roots {
+        left: diff.left_dir.as_ref().map(|dir| dir.path().clone()),
+        right: diff.right_dir.as_ref().map(|dir| dir.path().clone()),
+    };
+    let mut patch = String::new();
+    write_dir_patch(
+        &mut patch,
+        diff,
+        &roots,
+        context,
+        options,
+        &mut HashSet::new(),
+    )
+    .await?;
+    Ok(patch)
+}
+
+/// The paths of the directories compared at the top level.
+struct Roots {
+    left: Option<PathBuf>,
+    right: Option<PathBuf>,
+}
+
+impl Roots {
+    /// Returns the label of `file` on `side`.
+    fn label(&self, file: &FSItem, side: DiffSide) -> String {
+        let (root, prefix) = match side {
+            DiffSide::Left => (&self.left, "a"),
+            DiffSide::Right => (&self.right, "b"),
+        };
+        let path = root
+            .as_ref()
+            .and_then(|root| file.path().strip_prefix(root).ok())
+            .unwrap_or_else(|| Path::new(file.name()));
+        let components: Vec<_> = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        format!("{prefix}/{}", components.join("/"))
+    }
+}
+
+/// Appends the patch for the entries of `diff` and their subtrees to
+/// `patch`. Pairs of renamed files already written are recorded in
+/// `renamed`.
+fn write_dir_patch<'a>(
+    patch: &'a mut String,
+    diff: &'a DirDiff,
+    roots: &'a Roots,
+    context: usize,
+    options: &'a TextDiffOptions,
+    renamed: &'a mut HashSet<PathBuf>,
+) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>> {
+    // Boxed because directories are handled recursively.
+    Box::pin(async move {
+        for item in &diff.items {
+            let left_file = item.left_item.as_ref().filter(|i| i.is_file());
+            let right_file = item.right_item.as_ref().filter(|i| i.is_file());
+            match item.diff_item_type {
+                DiffItemType::Same { .. } => {}
+                DiffItemType::Renamed { .. } => {
+                    // Pairs found in different directories are listed on
+                    // both sides
+                    if let (Some(left), Some(right)) = (left_file, right_file)
+                        && renamed.insert(left.path().clone())
+                    {
+                        write_file_patch(
+                            patch,
+                            Some(left),
+                            None,
+                            roots,
+                            context,
+                            options,
+                        )
+                        .await?;
+                        write_file_patch(
+                            patch,
+                            None,
+                            Some(right),
+                            roots,
+                            context,
+                            options,
+                        )
+                        .await?;
+                    }
+                }
+                _ if left_file.is_some() || right_file.is_some() => {
+                    write_file_patch(
+                        patch, left_file, right_file, roots, context, options,
+                    )
+                    .await?;
+                }
+                _ => {}
+            }
+            if let Some(sub_diff) = &item.sub_diff {
+                write_dir_patch(
+                    patch, sub_diff, roots, context, options, renamed,
+                )
+                .await?;
+            } else if let Some(sub_diff) = compare_subdirs(item, diff).await? {
+                write_dir_patch(
+                    patch, &sub_diff, roots, context, options, renamed,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Compares the directories of `item`, an entry of `diff` not compared
+/// recursively, if there are any.
+async fn compare_subdirs(
+    item: &DiffItem,
+    diff: &DirDiff,
+) -> io::Result<Option<DirDiff>> {
+    let left_dir = item.left_item.clone().filter(FSItem::is_dir);
+    let right_dir = item.right_item.clone().filter(FSItem::is_dir);
+    if left_dir.is_none() && right_dir.is_none() {
+        return Ok(None);
+    }
+    let options = DiffOptions {
+        recursive: true,
+        detect_renames: false,
+        progress: None,
+        ..diff.options.clone()
+    };
+    Ok(Some(
+        DirDiff::with_options(&left_dir, &right_dir, options).await?,
+    ))
+}
+
+/// Appends the unified diff of the files `left` and `right`, at least one
+/// of them given, to `patch`.
+async fn write_file_patch(
+    patch: &mut String,
+    left: Option<&FSItem>,
+    right: Option<&FSItem>,
+    roots: &Roots,
+    context: usize,
+    options: &TextDiffOptions,
+) -> io::Result<()> {
+    let left_label = left.map(|file| roots.label(file, DiffSide::Left));
+    let right_label = right.map(|file| roots.label(file, DiffSide::Right));
+    if !left.into_iter().chain(right).all(is_text) {
+        patch.push_str(&format!(
+            "Binary files {} and {} differ\n",
+            left_label.as_deref().unwrap_or(NO_FILE),
+            right_label.as_deref().unwrap_or(NO_FILE),
+        ));
+        return Ok(());
+    }
+    let diff = TextDiff::with_options(
+        &left.cloned(),
+        &right.cloned(),
+        options.clone(),
+    )
+    .await?;
+    write_unified(
+        patch,
+        &diff,
+        left_label.as_deref(),
+        right_label.as_deref(),
+        context,
+    );
+    Ok(())
+}
+
+/// Returns `true` if `file` is empty or a text file.
+fn is_text(file: &FSItem) -> bool {
+    file.metadata().as_ref().is_some_and(|meta| meta.len() == 0)
+        || file
+            .file_type()
+            .is_some_and(|file_type| file_type.is_text())
+}
+
+/// Returns `true` if the text on `side` of `diff` is the unaltered content
+/// of its file, which is encoded in UTF-8 without byte order mark.
+fn is_verbatim(diff: &TextDiff, side: DiffSide) -> bool {
+    let encoding = match side {
+        DiffSide::Left => diff.left_encoding,
+        DiffSide::Right => diff.right_encoding,
+    };
+    encoding.encoding == UTF_8 && !encoding.bom && !diff.is_malformed(side)
+}
+
+/// Returns the header line of `file`: its label and its modification time.
+fn header(label: &str, file: &FSItem) -> String {
+    let mtime = file.modified().map_or_else(
+        || NO_TIME.to_string(),
+        |mtime| mtime.format("%Y-%m-%d %H:%M:%S%.9f %z").to_string(),
+    );
+    format!("{label}\t{mtime}")
+}
+
+/// Appends the unified diff of `diff` to `patch`, labelling the files by
+/// `left_label` and `right_label`, or as missing if `None`.
+fn write_unified(
+    patch: &mut String,
+    diff: &TextDiff,
+    left_label: Option<&str>,
+    right_label: Option<&str>,
+    context: usize,
+) {
+    let lines = patch_lines(diff);
+    let changes: Vec<usize> = (0..lines.len())
+        .filter(|&i| lines[i].tag != ' ' && !lines[i].ignored)
+        .collect();
+    if changes.is_empty() {
+        return;
+    }
+    if !(is_verbatim(diff, DiffSide::Left)
+        && is_verbatim(diff, DiffSide::Right))
+    {
+        patch.push_str(&format!(
+            "Files {} and {} differ\n",
+            left_label.unwrap_or(NO_FILE),
+            right_label.unwrap_or(NO_FILE),
+        ));
+        return;
+    }
+    let header = |label: Option<&str>, file: &FSItem| {
+        label.map_or_else(
+            || format!("{NO_FILE}\t{NO_TIME}"),
+            |label| header(label, file),
+        )
+    };
+    // Group changes separated by at most twice the context into hunks
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        match hunks.last_mut() {
+            Some((_, last)) if i - *last <= 2 * context + 1 => *last = i,
+            _ => hunks.push((i, i)),
+        }
+    }
+    // The numbers of old and new lines preceding each line
+    let mut positions = Vec::with_capacity(lines.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for line in &lines {
+        positions.push((old_pos, new_pos));
+        old_pos += usize::from(line.tag != '+');
+        new_pos += usize::from(line.tag != '-');
+    }
+    positions.push((old_pos, new_pos));
+
+    let mut out = format!(
+        "--- {}\n+++ {}\n",
+        header(left_label, &diff.left_file),
+        header(right_label, &diff.right_file)
+    );
+    for (first, last) in hunks {
+        let start = first.saturating_sub(context);
+        let end = (last + context + 1).min(lines.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+        for line in &lines[start..end] {
+            out.push(line.tag);
+            out.push_str(line.content);
+            if !line.content.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    patch.push_str(&out);
+}
+
+/// Returns the range of `len` lines following the first `start` lines in a
+/// hunk header, omitting a length of 1.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        // An empty range is given by the line preceding it
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+/// Returns the lines of `diff` in the order of a unified diff.
+fn patch_lines(diff: &TextDiff) -> Vec<PatchLine<'_>> {
+    let mut lines = Vec::new();
+    for chunk in &diff.chunks {
+        if chunk.diff_type == LineDiffType::Unchanged {
+            for (left, right) in
+                chunk.left_lines.iter().zip(&chunk.right_lines)
+            {
+                let (tag, line) = match (left.line_number, right.line_number) {
+                    (Some(_), Some(_)) => (' ', left),
+                    (Some(_), None) => ('-', left),
+                    (None, Some(_)) => ('+', right),
+                    (None, None) => continue,
+                };
+                lines.push(PatchLine {
+                    tag,
+                    content: &line.content,
+                    ignored: tag != ' ',
+                });
+            }
+            continue;
+        }
+        for (tag, side_lines) in
+            [('-', &chunk.left_lines), ('+', &chunk.right_lines)]
+        {
+            lines.extend(
+                side_lines
+                    .iter()
+                    .filter(|line| line.line_number.is_some())
+                    .map(|line| PatchLine {
+                        tag,
+                        content: &line.content,
+                        ignored: false,
+                    }),
+            );
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unified_diff() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let left = tmp.path().join("left.txt");
+        let right = tmp.path().join("right.txt");
+        let lines: Vec<String> = (1..=12).map(|i| format!("{i}\n")).collect();
+        fs::write(&left, lines.concat())?;
+        let mut changed = lines.clone();
+        changed[1] = "two\n".to_string();
+        changed.remove(10);
+        changed.push("13".to_string());
+        fs::write(&right, changed.concat())?;
+
+        let diff = TextDiff::new(
+            &Some(FSItem::new(&left).await),
+            &Some(FSItem::new(&right).await),
+        )
+        .await?;
+        let patch = unified_diff(&diff, 2);
+        let mut patch_lines = patch.lines();
+        let header = patch_lines.next().unwrap();
+        assert!(header.starts_with(&format!("--- {}\t", left.display())));
+        assert!(patch_lines.next().unwrap().starts_with("+++ "));
+        assert_eq!(
+            patch_lines.collect::<Vec<_>>(),
+            [
+                "@@ -1,4 +1,4 @@",
+                " 1",
+                "-2",
+                "+two",
+                " 3",
+                " 4",
+                "@@ -9,4 +9,4 @@",
+                " 9",
+                " 10",
+                "-11",
+                " 12",
+                "+13",
+                "\\ No newline at end of file",
+            ]
+        );
+        // Without any context, hunks are not merged
+        assert_eq!(unified_diff(&diff, 0).matches("@@ -").count(), 3);
+        assert!(unified_diff(&diff, 0).contains("@@ -11 +10,0 @@\n-11\n"));
+        // No differences, no patch
+        let same = TextDiff::new(
+            &Some(FSItem::new(&left).await),
+            &Some(FSItem::new(&left).await),
+        )
+        .await?;
+        assert!(unified_diff(&same, DEFAULT_CONTEXT).is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dir_patch() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let left = tmp.path().join("left");
+        let right = tmp.path().join("right");
+        fs::create_dir_all(left.join("sub"))?;
+        fs::create_dir_all(&right)?;
+        fs::write(left.join("same.txt"), "same\n")?;
+        fs::write(right.join("same.txt"), "same\n")?;
+        fs::write(left.join("changed.txt"), "a\nb\n")?;
+        fs::write(right.join("changed.txt"), "a\nc\n")?;
+        fs::write(left.join("sub/gone.txt"), "gone\n")?;
+        fs::write(right.join("new.txt"), "new\n")?;
+        fs::write(left.join("data.bin"), [0_u8, 1, 2, 0xff, 0])?;
+        fs::write(right.join("data.bin"), [0_u8, 1, 3, 0xff, 0])?;
+        // The byte order mark can't be part of the patch
+        fs::write(left.join("bom.txt"), "\u{feff}a\nb\n")?;
+        fs::write(right.join("bom.txt"), "\u{feff}a\nc\n")?;
+
+        let diff = DirDiff::with_options(
+            &Some(FSItem::new(&left).await),
+            &Some(FSItem::new(&right).await),
+            DiffOptions {
+                by_content: true,
+                ..Default::default()
+            },
+        )
+        .await?;
+        let patch =
+            patch(&diff, DEFAULT_CONTEXT, &TextDiffOptions::default()).await?;
+        let lines: Vec<&str> = patch
+            .lines()
+            // Strip the timestamps
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "--- a/sub/gone.txt",
+                "+++ /dev/null",
+                "@@ -1 +0,0 @@",
+                "-gone",
+                "Files a/bom.txt and b/bom.txt differ",
+                "--- a/changed.txt",
+                "+++ b/changed.txt",
+                "@@ -1,2 +1,2 @@",
+                " a",
+                "-b",
+                "+c",
+                "Binary files a/data.bin and b/data.bin differ",
+                "--- /dev/null",
+                "+++ b/new.txt",
+                "@@ -0,0 +1 @@",
+                "+new",
+            ]
+        );
+        Ok(())
+    }
+}
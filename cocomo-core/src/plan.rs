@@ -0,0 +1,336 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Planning Module (`plan`)
+//!
+//! This module provides a dry run of the file system operations in
+//! [`fsops`](crate::fsops): [`plan`] returns the list of actions an
+//! operation on one side of a [`DiffItem`] would perform, without touching
+//! the disk, so that they can be reviewed before being carried out.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cancel::CancelToken,
+    dirdiff::{DiffItem, DiffSide},
+    fsops::{
+        CopyOptions, FsError, Tree, copy_in, delete_in, move_in, rename_in,
+        trash_in,
+    },
+};
+
+/// File system operations which can be planned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    /// Copy the item to the directory `to`.
+    Copy { to: PathBuf },
+    /// Move the item to the directory `to`.
+    Move { to: PathBuf },
+    /// Delete the item permanently.
+    Delete,
+    /// Move the item to the trash.
+    Trash,
+    /// Rename the item.
+    Rename { new_name: String },
+}
+
+/// A single action performed by a file system operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Create the directory `path`.
+    CreateDir { path: PathBuf },
+    /// Copy the file `src` to `dst`, transferring `size` bytes.
+    CopyFile {
+        src: PathBuf,
+        dst: PathBuf,
+        size: u64,
+    },
+    /// Create a symbolic link at `path` referring to `target`.
+    CreateLink { path: PathBuf, target: PathBuf },
+    /// Create a hard link at `path` to the file `target`.
+    CreateHardLink { path: PathBuf, target: PathBuf },
+    /// Rename (move) `src` to `dst`.
+    Rename { src: PathBuf, dst: PathBuf },
+    /// Delete the file or link `path` of `size` bytes.
+    DeleteFile { path: PathBuf, size: u64 },
+    /// Delete the empty directory `path`.
+    DeleteDir { path: PathBuf },
+    /// Move the file or directory `path` to the trash.
+    Trash { path: PathBuf },
+    /// Leave out `src`, because `dst` already exists.
+    Skip { src: PathBuf, dst: PathBuf },
+}
+
+impl Action {
+    /// Returns the path created by this action, if any.
+    fn created(&self) -> Option<&Path> {
+        match self {
+            Self::CreateDir { path }
+            | Self::CreateLink { path, .. }
+            | Self::CreateHardLink { path, .. } => Some(path),
+            Self::CopyFile { dst, .. } | Self::Rename { dst, .. } => Some(dst),
+            _ => None,
+        }
+    }
+
+    /// Returns the path deleted by this action, if any.
+    fn deleted(&self) -> Option<&Path> {
+        match self {
+            Self::DeleteFile { path, .. }
+            | Self::DeleteDir { path }
+            | Self::Trash { path } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, form: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CreateDir { path } => {
+                write!(form, "create directory {}", path.display())
+            }
+            Self::CopyFile { src, dst, size } => write!(
+                form,
+                "copy {} to {} ({} bytes)",
+                src.display(),
+                dst.display(),
+                size
+            ),
+            Self::CreateLink { path, target } => write!(
+                form,
+                "create link {} -> {}",
+                path.display(),
+                target.display()
+            ),
+            Self::CreateHardLink { path, target } => write!(
+                form,
+                "create hard link {} to {}",
+                path.display(),
+                target.display()
+            ),
+            Self::Rename { src, dst } => {
+                write!(form, "move {} to {}", src.display(), dst.display())
+            }
+            Self::DeleteFile { path, .. } => {
+                write!(form, "delete {}", path.display())
+            }
+            Self::DeleteDir { path } => {
+                write!(form, "delete directory {}", path.display())
+            }
+            Self::Trash { path } => {
+                write!(form, "move {} to the trash", path.display())
+            }
+            Self::Skip { src, dst } => write!(
+                form,
+                "skip {} ({} exists)",
+                src.display(),
+                dst.display()
+            ),
+        }
+    }
+}
+
+/// The actions a file system operation would perform, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Plan {
+    /// The actions.
+    pub actions: Vec<Action>,
+}
+
+impl Plan {
+    /// Returns the number of bytes to be copied.
+    pub fn bytes(&self) -> u64 {
+        self.actions
+            .iter()
+            .map(|action| match action {
+                Action::CopyFile { size, .. } => *size,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Returns the paths of the items to be created, including those
+    /// replacing an existing item.
+    pub fn created(&self) -> Vec<&Path> {
+        self.actions.iter().filter_map(Action::created).collect()
+    }
+
+    /// Returns the paths of the existing items to be replaced.
+    pub fn overwritten(&self) -> Vec<&Path> {
+        let created = self.created();
+        self.actions
+            .iter()
+            .filter_map(Action::deleted)
+            .filter(|path| created.contains(path))
+            .collect()
+    }
+
+    /// Returns the paths of the items to be deleted without being replaced.
+    pub fn deleted(&self) -> Vec<&Path> {
+        let created = self.created();
+        self.actions
+            .iter()
+            .filter_map(Action::deleted)
+            .filter(|path| !created.contains(path))
+            .collect()
+    }
+}
+
+/// Returns the plan for applying `operation` to the item on the given
+/// `side` of `item`, using `options` like [`copy_item`] and [`move_item`]
+/// do.
+///
+/// Errors the operation would run into before changing anything, like
+/// conflicts to be resolved by asking, are returned as well. If there is no
+/// item on `side`, [`FsError::OtherSideOnly`] is returned.
+///
+/// [`copy_item`]: crate::fsops::copy_item
+/// [`move_item`]: crate::fsops::move_item
+pub async fn plan(
+    item: &DiffItem,
+    side: DiffSide,
+    operation: &Operation,
+    options: &CopyOptions,
+) -> Result<Plan, FsError> {
+    let (src, other) = match side {
+        DiffSide::Left => (&item.left_item, &item.right_item),
+        DiffSide::Right => (&item.right_item, &item.left_item),
+    };
+    let Some(src) = src else {
+        let path = other.as_ref().map(|item| item.path().clone());
+        return Err(FsError::OtherSideOnly(path.unwrap_or_default()));
+    };
+    let cancel = CancelToken::new();
+    let tree = Tree::new(src.path(), options, &cancel)?.planning();
+    match operation {
+        Operation::Copy { to } => copy_in(src, to, &tree).await?,
+        Operation::Move { to } => move_in(src, to, &tree).await?,
+        Operation::Delete => delete_in(src, &tree).await?,
+        Operation::Trash => trash_in(src, &tree).await?,
+        Operation::Rename { new_name } => {
+            rename_in(src, new_name, &tree).await?;
+        }
+    }
+    Ok(Plan {
+        actions: tree.into_actions(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use tokio::fs;
+
+    use super::*;
+    use crate::{fsitem::FSItem, fsops::ConflictPolicy};
+
+    #[tokio::test]
+    async fn test_plan() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let src_dir = tmp_dir.join("src");
+        let dst_dir = tmp_dir.join("dst");
+        fs::create_dir_all(src_dir.join("sub")).await?;
+        fs::create_dir_all(dst_dir.join("src")).await?;
+        fs::write(src_dir.join("a.txt"), "aaa").await?;
+        fs::write(src_dir.join("sub").join("b.txt"), "bb").await?;
+        fs::write(dst_dir.join("src").join("a.txt"), "old").await?;
+        let item = DiffItem::new(&Some(FSItem::new(&src_dir).await), &None)?;
+        let copy = Operation::Copy {
+            to: dst_dir.clone(),
+        };
+        // Conflicts to be resolved by asking are reported
+        assert!(matches!(
+            plan(&item, DiffSide::Left, &copy, &CopyOptions::default()).await,
+            Err(FsError::DestinationAlreadyExists(_))
+        ));
+        let options = CopyOptions {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        let copy_plan = plan(&item, DiffSide::Left, &copy, &options).await?;
+        let target = dst_dir.join("src");
+        assert_eq!(copy_plan.bytes(), 5);
+        assert!(copy_plan.actions.contains(&Action::CreateDir {
+            path: target.join("sub")
+        }));
+        assert!(copy_plan.actions.contains(&Action::CopyFile {
+            src: src_dir.join("sub").join("b.txt"),
+            dst: target.join("sub").join("b.txt"),
+            size: 2
+        }));
+        assert_eq!(copy_plan.overwritten(), vec![target.join("a.txt")]);
+        assert!(copy_plan.deleted().is_empty());
+        let delete_plan =
+            plan(&item, DiffSide::Left, &Operation::Delete, &options).await?;
+        assert_eq!(
+            delete_plan.actions.last(),
+            Some(&Action::DeleteDir {
+                path: src_dir.clone()
+            })
+        );
+        assert_eq!(delete_plan.deleted().len(), 4);
+        assert_eq!(delete_plan.bytes(), 0);
+        let trash_plan =
+            plan(&item, DiffSide::Left, &Operation::Trash, &options).await?;
+        assert_eq!(
+            trash_plan.actions,
+            vec![Action::Trash {
+                path: src_dir.clone()
+            }]
+        );
+        let move_plan = plan(
+            &item,
+            DiffSide::Left,
+            &Operation::Move {
+                to: dst_dir.clone(),
+            },
+            &options,
+        )
+        .await?;
+        // The order of the entries depends on the file system
+        assert_eq!(move_plan.actions.len(), 4);
+        for action in [
+            Action::DeleteFile {
+                path: target.join("a.txt"),
+                size: 3,
+            },
+            Action::Rename {
+                src: src_dir.join("a.txt"),
+                dst: target.join("a.txt"),
+            },
+            Action::Rename {
+                src: src_dir.join("sub"),
+                dst: target.join("sub"),
+            },
+        ] {
+            assert!(move_plan.actions.contains(&action));
+        }
+        assert_eq!(
+            move_plan.actions.last(),
+            Some(&Action::DeleteDir {
+                path: src_dir.clone()
+            })
+        );
+        // Nothing has been touched
+        assert_eq!(fs::read_to_string(target.join("a.txt")).await?, "old");
+        assert!(!target.join("sub").exists());
+        assert!(src_dir.join("sub").join("b.txt").exists());
+        // No item on the given side
+        assert!(matches!(
+            plan(&item, DiffSide::Right, &Operation::Delete, &options).await,
+            Err(FsError::OtherSideOnly(path)) if path == src_dir
+        ));
+        Ok(())
+    }
+}
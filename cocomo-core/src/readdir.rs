@@ -12,21 +12,178 @@
 //! This internal module provides a helper for asynchronously reading the
 //! contents of a directory and wrapping them into [`FSItem`] objects.
 
-use tokio::{fs, io};
+use std::path::{Path, PathBuf};
 
-use crate::fsitem::FSItem;
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use tokio::{fs, io, task};
+
+use crate::{
+    fsitem::FSItem,
+    scan::{Scan, ScanEvent},
+};
+
+/// Names of the ignore files honoured, in order of precedence.
+const IGNORE_FILES: [&str; 2] = [".ignore", ".gitignore"];
+
+/// Filter for excluding entries when reading directories.
+#[derive(Clone, Debug)]
+pub struct ItemFilter {
+    /// Matcher for the patterns of entries to exclude.
+    excludes: Gitignore,
+    /// Honour `.gitignore` and `.ignore` files.
+    use_ignore_files: bool,
+    /// The roots of the compared trees; ignore files above them are not
+    /// honoured.
+    roots: Vec<PathBuf>,
+}
+
+impl Default for ItemFilter {
+    fn default() -> Self {
+        Self {
+            excludes: Gitignore::empty(),
+            use_ignore_files: false,
+            roots: Vec::new(),
+        }
+    }
+}
+
+impl ItemFilter {
+    /// Creates a filter excluding the entries matching one of `patterns`.
+    ///
+    /// The patterns are given in gitignore syntax (e.g. `target/`, `*.o`,
+    /// `.DS_Store`) and matched against the names of the entries; a
+    /// trailing `/` restricts a pattern to directories and a leading `!`
+    /// re-includes entries excluded by a previous pattern. With
+    /// `use_ignore_files` set, `.gitignore` and `.ignore` files found in the
+    /// compared trees are honoured as well.
+    ///
+    /// Returns an error of kind `InvalidInput` if a pattern is invalid.
+    pub fn new(
+        patterns: &[impl AsRef<str>],
+        use_ignore_files: bool,
+    ) -> io::Result<Self> {
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidInput, err);
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in patterns {
+            builder.add_line(None, pattern.as_ref()).map_err(invalid)?;
+        }
+        Ok(Self {
+            excludes: builder.build().map_err(invalid)?,
+            use_ignore_files,
+            roots: Vec::new(),
+        })
+    }
+
+    /// Returns the filter honouring only the ignore files in the
+    /// directories `roots` and below them.
+    ///
+    /// If not given, the roots are the directories compared by a
+    /// [`DirDiff`](crate::DirDiff).
+    #[must_use]
+    pub fn with_roots(
+        mut self,
+        roots: impl IntoIterator<Item = PathBuf>,
+    ) -> Self {
+        self.roots = roots.into_iter().collect();
+        self
+    }
+
+    /// Returns `true` if the roots of the compared trees have been given.
+    pub(crate) fn has_roots(&self) -> bool {
+        !self.roots.is_empty()
+    }
+}
+
+/// Returns the matchers of the ignore files applying to the entries of
+/// `dir`, i.e. those in `dir` and in its parents up to the root containing
+/// it, ordered by precedence.
+fn ignore_matchers(dir: &Path, roots: &[PathBuf]) -> Vec<Gitignore> {
+    let root = roots
+        .iter()
+        .filter(|root| dir.starts_with(root))
+        .max_by_key(|root| root.components().count());
+    let mut matchers = Vec::new();
+    for ancestor in dir.ancestors() {
+        for name in IGNORE_FILES {
+            let path = ancestor.join(name);
+            if path.is_file() {
+                let mut builder = GitignoreBuilder::new(ancestor);
+                // Invalid lines are skipped, as git does
+                let _ = builder.add(path);
+                if let Ok(matcher) = builder.build() {
+                    matchers.push(matcher);
+                }
+            }
+        }
+        if root.is_none_or(|root| ancestor == root) {
+            break;
+        }
+    }
+    matchers
+}
+
+/// Returns `true` if the entry at `path` is excluded by the first of
+/// `matchers` matching it.
+fn is_ignored(matchers: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    for matcher in matchers {
+        match matcher.matched(path, is_dir) {
+            Match::None => {}
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+        }
+    }
+    false
+}
 
 /// Reads the contents of a directory and returns a vector of [`FSItem`]s.
 ///
 /// If `dir` is a symbolic link, it is first resolved to its target directory.
-pub(crate) async fn read_dir(dir: &FSItem) -> io::Result<Vec<FSItem>> {
+/// Entries excluded by `filter` are skipped. The items are created
+/// concurrently, limited by `scan`, which also receives a progress event for
+/// each item.
+pub(crate) async fn read_dir(
+    dir: &FSItem,
+    filter: &ItemFilter,
+    scan: &Scan,
+) -> io::Result<Vec<FSItem>> {
+    // Ignore files apply to the paths within the compared trees
+    let tree_path = dir.path().clone();
+    let matchers = if filter.use_ignore_files {
+        let roots = filter.roots.clone();
+        let tree_path = tree_path.clone();
+        task::spawn_blocking(move || ignore_matchers(&tree_path, &roots))
+            .await?
+    } else {
+        Vec::new()
+    };
     let dir = dir.unlink().await.into_owned();
-    let path = dir.path();
-    let mut items = Vec::new();
-    let mut rd = fs::read_dir(path).await?;
+    let mut tasks = Vec::new();
+    let mut rd = fs::read_dir(dir.path()).await?;
     while let Some(entry) = rd.next_entry().await? {
-        let item = FSItem::new(entry.path()).await;
-        items.push(item);
+        scan.check()?;
+        let is_dir = entry.file_type().await?.is_dir();
+        let name = entry.file_name();
+        if filter.excludes.matched(&name, is_dir).is_ignore()
+            || is_ignored(&matchers, &tree_path.join(&name), is_dir)
+        {
+            continue;
+        }
+        let path = entry.path();
+        let scan = scan.clone();
+        tasks.push(task::spawn(async move {
+            let _permit = scan.permit().await;
+            scan.check()?;
+            let item = FSItem::new(&path).await;
+            scan.report(ScanEvent::Scanned(path));
+            io::Result::Ok(item)
+        }));
+    }
+    let mut items = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        items.push(task.await??);
     }
     Ok(items)
 }
@@ -34,12 +191,90 @@ pub(crate) async fn read_dir(dir: &FSItem) -> io::Result<Vec<FSItem>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cancel::CancelToken;
 
     #[tokio::test]
     async fn test_readdir() {
-        let content = read_dir(&FSItem::new("..").await)
-            .await
-            .expect("Error reading '..'");
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let scan = Scan::new(2, Some(sender), CancelToken::new());
+        let content =
+            read_dir(&FSItem::new("..").await, &ItemFilter::default(), &scan)
+                .await
+                .expect("Error reading '..'");
         assert!(!content.is_empty());
+        // Items are created concurrently, so the order of events is
+        // arbitrary
+        for _ in &content {
+            let Ok(ScanEvent::Scanned(path)) = receiver.try_recv() else {
+                panic!("Missing scan event");
+            };
+            assert!(content.iter().any(|item| item.path() == &path));
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_readdir_filtered() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let tmp = tempfile::tempdir()?;
+        let tmp_dir = tmp.path();
+        fs::create_dir_all(tmp_dir.join("target").join("debug")).await?;
+        fs::create_dir(tmp_dir.join("src")).await?;
+        fs::write(tmp_dir.join("main.o"), "").await?;
+        fs::write(tmp_dir.join("keep.o"), "").await?;
+        fs::write(tmp_dir.join("target.txt"), "").await?;
+        fs::write(tmp_dir.join("generated.rs"), "").await?;
+        fs::write(tmp_dir.join(".gitignore"), "generated.rs\n").await?;
+        fs::write(tmp_dir.join("src").join("generated.rs"), "").await?;
+        let names = async |dir: &std::path::Path, filter: &ItemFilter| {
+            let mut names: Vec<String> = read_dir(
+                &FSItem::new(dir).await,
+                filter,
+                &Scan::new(0, None, CancelToken::new()),
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|item| item.name().to_string_lossy().into_owned())
+            .collect();
+            names.sort();
+            names
+        };
+        let filter = ItemFilter::new(&["target/", "*.o", "!keep.o"], false)?;
+        assert_eq!(
+            names(tmp_dir, &filter).await,
+            [".gitignore", "generated.rs", "keep.o", "src", "target.txt"]
+        );
+        let filter = ItemFilter::new(&["target/", "*.o", "!keep.o"], true)?
+            .with_roots([tmp_dir.to_path_buf()]);
+        assert_eq!(
+            names(tmp_dir, &filter).await,
+            [".gitignore", "keep.o", "src", "target.txt"]
+        );
+        // Ignore files in parent dirs within the compared tree apply as well
+        assert!(names(&tmp_dir.join("src"), &filter).await.is_empty());
+        // ... but not those above it
+        let sub_filter = ItemFilter::new(&[] as &[&str], true)?
+            .with_roots([tmp_dir.join("src")]);
+        assert_eq!(
+            names(&tmp_dir.join("src"), &sub_filter).await,
+            ["generated.rs"]
+        );
+        // Cancelled scans are reported as errors
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let result = read_dir(
+            &FSItem::new(tmp_dir).await,
+            &filter,
+            &Scan::new(0, None, cancel),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        // Invalid patterns are rejected
+        assert_eq!(
+            ItemFilter::new(&["a{b"], false).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,317 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Rename Detection Module (`renames`)
+//!
+//! This internal module pairs files found only on the left side of a
+//! directory comparison with files found only on the right side, which are
+//! likely to be the same file renamed or moved to another directory.
+//!
+//! Files of equal size are paired if their content hashes match. Optionally,
+//! the text files remaining unpaired are compared line by line and paired
+//! with the most similar file, if the similarity reaches a given threshold.
+//! Each pair replaces both single-sided entries by an entry of type
+//! [`DiffItemType::Renamed`] holding both files. If both files reside in the
+//! same directory, the pair is listed only once.
+
+use std::collections::HashMap;
+
+use tokio::{fs, io, task};
+
+use crate::{
+    content::content_hash,
+    dirdiff::{DiffItem, DiffItemType, DirDiff},
+    encoding::TextEncoding,
+    fsitem::{FSItem, FileType},
+    scan::{Scan, ScanEvent},
+};
+
+/// Maximum size of text files compared by similarity.
+const MAX_SIMILAR_SIZE: u64 = 1024 * 1024;
+
+/// Maximum number of pairs of text files compared by similarity.
+const MAX_SIMILAR_PAIRS: usize = 10_000;
+
+/// Position of an entry in a tree of comparisons: the indices of the entry
+/// and of the directory entries leading to it.
+type Position = Vec<usize>;
+
+/// A file found on one side only.
+struct Candidate {
+    position: Position,
+    item: FSItem,
+}
+
+/// Pairs the files found only on one side in `items` and their subtrees,
+/// as described in the module documentation. Text files are compared by
+/// similarity if `min_similarity` is greater than 0.
+pub(crate) async fn detect_renames(
+    items: &mut Vec<DiffItem>,
+    min_similarity: f32,
+    scan: &Scan,
+) -> io::Result<()> {
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    collect_candidates(items, &mut Vec::new(), &mut left, &mut right);
+    if left.is_empty() || right.is_empty() {
+        return Ok(());
+    }
+    let mut pairs = pair_by_content(&left, &right, scan).await?;
+    if min_similarity > 0.0 {
+        let (left_paired, right_paired): (Vec<_>, Vec<_>) =
+            pairs.iter().map(|&(l, r, _)| (l, r)).unzip();
+        let left_rest: Vec<_> = (0..left.len())
+            .filter(|i| !left_paired.contains(i))
+            .collect();
+        let right_rest: Vec<_> = (0..right.len())
+            .filter(|i| !right_paired.contains(i))
+            .collect();
+        pairs.extend(
+            pair_by_similarity(
+                &left,
+                &left_rest,
+                &right,
+                &right_rest,
+                min_similarity,
+                scan,
+            )
+            .await?,
+        );
+    }
+    let mut removed = Vec::new();
+    for (l, r, identical) in pairs {
+        let (left, right) = (&left[l], &right[r]);
+        let pair = DiffItem {
+            diff_item_type: DiffItemType::Renamed { identical },
+            left_item: Some(left.item.clone()),
+            right_item: Some(right.item.clone()),
+            sub_diff: None,
+            attr_diff: None,
+        };
+        *item_at(items, &left.position) = pair.clone();
+        let (left_dir, _) = left.position.split_at(left.position.len() - 1);
+        let (right_dir, _) = right.position.split_at(right.position.len() - 1);
+        if left_dir == right_dir {
+            removed.push(right.position.clone());
+        } else {
+            *item_at(items, &right.position) = pair;
+        }
+    }
+    // Removing the entries in reverse order keeps the positions of the
+    // remaining ones valid.
+    removed.sort_unstable_by(|a, b| b.cmp(a));
+    for position in removed {
+        let (&index, dir) = position.split_last().unwrap();
+        items_at(items, dir).remove(index);
+    }
+    update_status(items);
+    Ok(())
+}
+
+/// Collects the non-empty files found only on the left side and only on the
+/// right side in `items` and their subtrees.
+fn collect_candidates(
+    items: &[DiffItem],
+    position: &mut Position,
+    left: &mut Vec<Candidate>,
+    right: &mut Vec<Candidate>,
+) {
+    for (i, item) in items.iter().enumerate() {
+        position.push(i);
+        let candidate =
+            match (item.diff_item_type, &item.left_item, &item.right_item) {
+                (DiffItemType::LeftOnly, Some(file), _) => {
+                    Some((file, &mut *left))
+                }
+                (DiffItemType::RightOnly, _, Some(file)) => {
+                    Some((file, &mut *right))
+                }
+                _ => None,
+            };
+        if let Some((file, candidates)) = candidate
+            && file.is_file()
+            && file.metadata().as_ref().is_some_and(|meta| meta.len() > 0)
+        {
+            candidates.push(Candidate {
+                position: position.clone(),
+                item: file.clone(),
+            });
+        }
+        if let Some(sub_diff) = &item.sub_diff {
+            collect_candidates(&sub_diff.items, position, left, right);
+        }
+        position.pop();
+    }
+}
+
+/// Returns the length of the file `candidate`.
+fn len(candidate: &Candidate) -> u64 {
+    candidate
+        .item
+        .metadata()
+        .as_ref()
+        .map_or(0, |meta| meta.len())
+}
+
+/// Returns the pairs of indices of `left` and `right` files with identical
+/// content, together with `true`.
+///
+/// Files with the same name are preferred, so that files moved to another
+/// directory are paired correctly, even if there are several copies.
+async fn pair_by_content(
+    left: &[Candidate],
+    right: &[Candidate],
+    scan: &Scan,
+) -> io::Result<Vec<(usize, usize, bool)>> {
+    let mut by_len: HashMap<u64, (Vec<usize>, Vec<usize>)> = HashMap::new();
+    for (i, candidate) in left.iter().enumerate() {
+        by_len.entry(len(candidate)).or_default().0.push(i);
+    }
+    for (i, candidate) in right.iter().enumerate() {
+        if let Some((_, right)) = by_len.get_mut(&len(candidate)) {
+            right.push(i);
+        }
+    }
+    let hash = async |candidate: &Candidate| {
+        let _permit = scan.permit().await;
+        content_hash(candidate.item.path(), |n| {
+            scan.report(ScanEvent::Read(n));
+            scan.check()
+        })
+        .await
+    };
+    let mut pairs = Vec::new();
+    for (left_indices, right_indices) in by_len.into_values() {
+        if right_indices.is_empty() {
+            continue;
+        }
+        let mut right_hashes = Vec::with_capacity(right_indices.len());
+        for &r in &right_indices {
+            right_hashes.push(Some(hash(&right[r]).await?));
+        }
+        for &l in &left_indices {
+            let left_hash = hash(&left[l]).await?;
+            let matching: Vec<_> = (0..right_indices.len())
+                .filter(|&j| right_hashes[j] == Some(left_hash))
+                .collect();
+            let best = matching
+                .iter()
+                .find(|&&j| {
+                    right[right_indices[j]].item.name() == left[l].item.name()
+                })
+                .or(matching.first());
+            if let Some(&j) = best {
+                right_hashes[j] = None;
+                pairs.push((l, right_indices[j], true));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Returns the pairs of indices of the text files among `left[left_rest]`
+/// and `right[right_rest]` whose similarity is at least `min_similarity`,
+/// together with `false`.
+///
+/// Each file is paired with the most similar one still available.
+async fn pair_by_similarity(
+    left: &[Candidate],
+    left_rest: &[usize],
+    right: &[Candidate],
+    right_rest: &[usize],
+    min_similarity: f32,
+    scan: &Scan,
+) -> io::Result<Vec<(usize, usize, bool)>> {
+    let read_texts = async |candidates: &[Candidate], indices: &[usize]| {
+        let mut texts = Vec::new();
+        for &i in indices {
+            let item = &candidates[i].item;
+            if item.file_type() != Some(FileType::TEXT)
+                || len(&candidates[i]) > MAX_SIMILAR_SIZE
+            {
+                continue;
+            }
+            scan.check()?;
+            let content = fs::read(item.path()).await?;
+            scan.report(ScanEvent::Read(content.len() as u64));
+            texts.push((i, TextEncoding::detect(&content).decode(&content).0));
+        }
+        Ok::<_, io::Error>(texts)
+    };
+    let left_texts = read_texts(left, left_rest).await?;
+    let right_texts = read_texts(right, right_rest).await?;
+    if left_texts.is_empty()
+        || right_texts.is_empty()
+        || left_texts.len() * right_texts.len() > MAX_SIMILAR_PAIRS
+    {
+        return Ok(Vec::new());
+    }
+    let scan = scan.clone();
+    task::spawn_blocking(move || {
+        let mut ratios = Vec::new();
+        for (l, left_text) in &left_texts {
+            for (r, right_text) in &right_texts {
+                scan.check()?;
+                let ratio =
+                    similar::TextDiff::from_lines(left_text, right_text)
+                        .ratio();
+                if ratio >= min_similarity {
+                    ratios.push((ratio, *l, *r));
+                }
+            }
+        }
+        ratios.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut pairs: Vec<(usize, usize, bool)> = Vec::new();
+        for (_, l, r) in ratios {
+            if !pairs.iter().any(|&(pl, pr, _)| pl == l || pr == r) {
+                pairs.push((l, r, false));
+            }
+        }
+        Ok(pairs)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Returns the list of entries of the directory at `position` in `items`.
+fn items_at<'a>(
+    items: &'a mut Vec<DiffItem>,
+    position: &[usize],
+) -> &'a mut Vec<DiffItem> {
+    match position.split_first() {
+        Some((&index, rest)) => {
+            let sub_diff: &mut DirDiff =
+                items[index].sub_diff.as_mut().expect("directory entry");
+            items_at(&mut sub_diff.items, rest)
+        }
+        None => items,
+    }
+}
+
+/// Returns the entry at `position` in `items`.
+fn item_at<'a>(
+    items: &'a mut Vec<DiffItem>,
+    position: &[usize],
+) -> &'a mut DiffItem {
+    let (&index, dir) = position.split_last().expect("non-empty position");
+    &mut items_at(items, dir)[index]
+}
+
+/// Updates the aggregated status of the directories existing on both sides
+/// in `items` and their subtrees.
+fn update_status(items: &mut [DiffItem]) {
+    for item in items {
+        if let Some(sub_diff) = &mut item.sub_diff {
+            update_status(&mut sub_diff.items);
+            if sub_diff.left_dir.is_some() && sub_diff.right_dir.is_some() {
+                item.diff_item_type = sub_diff.status();
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Scan Module (`scan`)
+//!
+//! This module provides the progress events emitted while comparing
+//! directories and the internal state shared by all tasks of a comparison.
+
+use std::{io, num::NonZeroUsize, path::PathBuf, sync::Arc, thread};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+
+use crate::cancel::CancelToken;
+
+/// Progress events emitted while comparing directories.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScanEvent {
+    /// An entry has been read from a directory.
+    Scanned(PathBuf),
+    /// The given number of bytes has been read for comparing file content.
+    Read(u64),
+    /// The given directory has been compared completely.
+    Compared(PathBuf),
+}
+
+/// Sending half of a channel receiving [`ScanEvent`]s.
+pub type ScanEventSender = mpsc::UnboundedSender<ScanEvent>;
+
+/// State shared by all tasks of a single comparison.
+#[derive(Clone, Debug)]
+pub(crate) struct Scan {
+    limiter: Arc<Semaphore>,
+    progress: Option<ScanEventSender>,
+    cancel: CancelToken,
+}
+
+impl Scan {
+    /// Creates a new `Scan` running at most `parallelism` I/O bound tasks
+    /// concurrently (0 means: number of available CPUs), reporting progress
+    /// to `progress`, if given, and stopping when `cancel` gets cancelled.
+    pub(crate) fn new(
+        parallelism: usize,
+        progress: Option<ScanEventSender>,
+        cancel: CancelToken,
+    ) -> Self {
+        let permits = if parallelism == 0 {
+            thread::available_parallelism().map_or(1, NonZeroUsize::get)
+        } else {
+            parallelism
+        };
+        Self {
+            limiter: Arc::new(Semaphore::new(permits)),
+            progress,
+            cancel,
+        }
+    }
+
+    /// Waits until another I/O bound task may run and returns a permit which
+    /// has to be held while the task is running.
+    pub(crate) async fn permit(&self) -> OwnedSemaphorePermit {
+        // The semaphore is never closed, so acquiring can't fail.
+        self.limiter.clone().acquire_owned().await.unwrap()
+    }
+
+    /// Returns an error of kind `Interrupted` if the scan has been
+    /// cancelled.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        self.cancel.check()
+    }
+
+    /// Reports a progress event.
+    pub(crate) fn report(&self, event: ScanEvent) {
+        if let Some(progress) = &self.progress {
+            // The receiver may have been dropped, which is not an error.
+            let _ = progress.send(event);
+        }
+    }
+}
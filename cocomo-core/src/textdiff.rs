@@ -11,12 +11,30 @@
 //!
 //! This module provides the logic for comparing two text files line by line.
 //! It computes the differences and prepares them for side-by-side display.
+//! Files are decoded according to their detected or given encoding, so that
+//! files in different encodings are compared by their characters.
+//!
+//! The texts can be merged chunk by chunk, by copying the lines of a chunk
+//! to the other side or deleting them, and be saved back to their files.
 
-use std::{ffi, fs, io};
+use std::{
+    ffi, fs,
+    io::{self, Read},
+    ops::Range,
+};
 
+use encoding_rs::Encoding;
 use similar;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    CancelToken, DiffSide, FSItem,
+    encoding::TextEncoding,
+    fsops::{create_temp_file, sync_dir},
+};
 
-use crate::FSItem;
+/// Number of bytes read at once between checks for cancellation.
+const READ_SIZE: u64 = 1024 * 1024;
 
 /// The type of change for a single line in a file comparison.
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -31,14 +49,58 @@ pub enum LineDiffType {
     Changed,
 }
 
+/// Modes for comparing whitespace within lines.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum WhitespaceMode {
+    /// Whitespace has to match exactly.
+    #[default]
+    Exact,
+    /// Whitespace at the end of lines is ignored.
+    IgnoreTrailing,
+    /// Changes in the amount of whitespace are ignored: runs of whitespace
+    /// match each other, e.g. a tab matches spaces, and whitespace at the end
+    /// of lines is ignored.
+    IgnoreAmount,
+    /// All whitespace is ignored.
+    IgnoreAll,
+}
+
+/// Options for comparing two text files.
+///
+/// The options only affect which lines are regarded as matching; the lines
+/// of the comparison result keep their original content.
+#[derive(Clone, Debug, Default)]
+pub struct TextDiffOptions {
+    /// How to compare whitespace within lines.
+    pub whitespace: WhitespaceMode,
+    /// Regard removed and added lines as unchanged if they are all blank.
+    pub ignore_blank_lines: bool,
+    /// Compare lines case-insensitively.
+    pub ignore_case: bool,
+    /// Regard the line terminators `\n` and `\r\n` as equal, as well as a
+    /// missing terminator at the end of a file.
+    pub normalize_line_endings: bool,
+    /// The encoding of the left file, detected if not given.
+    pub left_encoding: Option<&'static Encoding>,
+    /// The encoding of the right file, detected if not given.
+    pub right_encoding: Option<&'static Encoding>,
+    /// Token for cancelling the comparison. A cancelled comparison fails
+    /// with an error of kind `Interrupted`.
+    pub cancel: CancelToken,
+}
+
 /// A single line in a file comparison result, potentially representing a
 /// placeholder if the line only exists on the other side.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct DiffLine {
     /// The line number (1-based).
     pub line_number: Option<usize>,
     /// The content of the line.
     pub content: String,
+    /// The byte ranges of `content` differing from the paired line on the
+    /// other side, ordered and non-overlapping. Only set for lines of a
+    /// `Changed` chunk.
+    pub inline_changes: Vec<Range<usize>>,
 }
 
 /// A chunk of adjacent lines with the same diff type.
@@ -52,6 +114,16 @@ pub struct DiffChunk {
     pub right_lines: Vec<DiffLine>,
 }
 
+impl DiffChunk {
+    /// Returns the lines of the chunk on `side`.
+    pub fn lines(&self, side: DiffSide) -> &[DiffLine] {
+        match side {
+            DiffSide::Left => &self.left_lines,
+            DiffSide::Right => &self.right_lines,
+        }
+    }
+}
+
 /// A complete result of a comparison between two text files.
 #[derive(Clone, Debug)]
 pub struct TextDiff {
@@ -59,180 +131,309 @@ pub struct TextDiff {
     pub left_file: FSItem,
     /// The source file on the right side.
     pub right_file: FSItem,
+    /// The encoding of the left file.
+    pub left_encoding: TextEncoding,
+    /// The encoding of the right file.
+    pub right_encoding: TextEncoding,
     /// The list of compared chunks.
     pub chunks: Vec<DiffChunk>,
+    /// The options used for the comparison.
+    pub options: TextDiffOptions,
+    /// The current text on the left side.
+    left_text: SideText,
+    /// The current text on the right side.
+    right_text: SideText,
+}
+
+/// The current text on one side of a comparison.
+#[derive(Clone, Debug, Default)]
+struct SideText {
+    /// The decoded content, including modifications.
+    content: String,
+    /// The file contained sequences invalid in its encoding, which have
+    /// been replaced when decoding.
+    malformed: bool,
+    /// The content has been modified since it was read or saved.
+    modified: bool,
+}
+
+impl TextDiffOptions {
+    /// Returns the key `line` is matched by, with its content normalized
+    /// according to the options.
+    fn key(&self, line: &str) -> String {
+        let (content, terminator) = split_terminator(line);
+        let mut key = match self.whitespace {
+            WhitespaceMode::Exact => content.to_string(),
+            WhitespaceMode::IgnoreTrailing => content.trim_end().to_string(),
+            WhitespaceMode::IgnoreAmount => {
+                let mut key = String::with_capacity(content.len());
+                let mut in_whitespace = false;
+                for c in content.trim_end().chars() {
+                    if c.is_whitespace() {
+                        if !in_whitespace {
+                            key.push(' ');
+                        }
+                        in_whitespace = true;
+                    } else {
+                        key.push(c);
+                        in_whitespace = false;
+                    }
+                }
+                key
+            }
+            WhitespaceMode::IgnoreAll => {
+                content.chars().filter(|c| !c.is_whitespace()).collect()
+            }
+        };
+        if self.ignore_case {
+            key = key.to_lowercase();
+        }
+        if !self.normalize_line_endings {
+            key.push_str(terminator);
+        }
+        key
+    }
+}
+
+/// Splits `line` into its content and its line terminator.
+fn split_terminator(line: &str) -> (&str, &str) {
+    let content = line
+        .strip_suffix('\n')
+        .map_or(line, |line| line.strip_suffix('\r').unwrap_or(line));
+    line.split_at(content.len())
+}
+
+/// Returns the byte ranges of the segments emphasized in `change`, merging
+/// adjacent ones and excluding the line terminator.
+///
+/// If `similar` found the lines too different to emphasize single words, the
+/// whole line is regarded as changed.
+fn emphasized(change: &similar::InlineChange<'_, str>) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut pos = 0;
+    for (emphasized, value) in change.iter_strings_lossy() {
+        let end = pos + split_terminator(&value).0.len();
+        if emphasized && end > pos {
+            match ranges.last_mut() {
+                Some(last) if last.end == pos => last.end = end,
+                _ => ranges.push(pos..end),
+            }
+        }
+        pos += value.len();
+    }
+    if ranges.is_empty() {
+        let content: String = change
+            .iter_strings_lossy()
+            .map(|(_, value)| value)
+            .collect();
+        let len = split_terminator(&content).0.len();
+        if len > 0 {
+            ranges.push(0..len);
+        }
+    }
+    ranges
+}
+
+/// Returns the byte ranges of the segments differing between the lines
+/// `left` and `right`.
+fn inline_changes(
+    left: &str,
+    right: &str,
+) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let diff = similar::TextDiff::from_lines(left, right);
+    let mut left_changes = Vec::new();
+    let mut right_changes = Vec::new();
+    for op in diff.ops() {
+        for change in diff.iter_inline_changes(op) {
+            match change.tag() {
+                similar::ChangeTag::Delete => {
+                    left_changes = emphasized(&change)
+                }
+                similar::ChangeTag::Insert => {
+                    right_changes = emphasized(&change);
+                }
+                similar::ChangeTag::Equal => {}
+            }
+        }
+    }
+    (left_changes, right_changes)
+}
+
+/// Returns the lines in `range` of `lines`.
+fn diff_lines(lines: &[&str], range: Range<usize>) -> Vec<DiffLine> {
+    range
+        .map(|i| DiffLine {
+            line_number: Some(i + 1),
+            content: lines[i].to_string(),
+            inline_changes: Vec::new(),
+        })
+        .collect()
+}
+
+/// Returns a chunk of the given type, padding the shorter side with
+/// placeholders.
+fn make_chunk(
+    diff_type: LineDiffType,
+    mut left_lines: Vec<DiffLine>,
+    mut right_lines: Vec<DiffLine>,
+) -> DiffChunk {
+    let len = left_lines.len().max(right_lines.len());
+    left_lines.resize(len, DiffLine::default());
+    right_lines.resize(len, DiffLine::default());
+    DiffChunk {
+        diff_type,
+        left_lines,
+        right_lines,
+    }
+}
+
+/// Reads and decodes the content of `file` in the given `encoding`, or in
+/// the one detected if `None`. A missing file is regarded as empty.
+///
+/// Reading fails with an error of kind `Interrupted` if `cancel` has been
+/// cancelled.
+fn read_text(
+    file: Option<&FSItem>,
+    encoding: Option<&'static Encoding>,
+    cancel: &CancelToken,
+) -> io::Result<(TextEncoding, SideText)> {
+    let Some(file) = file else {
+        return Ok((TextEncoding::default(), SideText::default()));
+    };
+    let mut reader = fs::File::open(file.path())?;
+    let mut bytes = Vec::new();
+    loop {
+        cancel.check()?;
+        let n = (&mut reader).take(READ_SIZE).read_to_end(&mut bytes)?;
+        if n == 0 {
+            break;
+        }
+    }
+    let encoding = encoding.map_or_else(
+        || TextEncoding::detect(&bytes),
+        |encoding| TextEncoding::with_encoding(encoding, &bytes),
+    );
+    let (content, malformed) = encoding.decode(&bytes);
+    Ok((
+        encoding,
+        SideText {
+            content,
+            malformed,
+            modified: false,
+        },
+    ))
+}
+
+/// Returns `text` with the lines in `range` replaced by `lines`.
+///
+/// Lines getting followed by other lines are terminated if necessary, using
+/// the line terminator of the first line of `text`.
+fn splice_lines(text: &str, range: Range<usize>, lines: &[&str]) -> String {
+    let mut all_lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let terminator = match all_lines.first() {
+        Some(line) if line.ends_with("\r\n") => "\r\n",
+        _ => "\n",
+    };
+    all_lines.splice(range, lines.iter().copied());
+    let mut result = String::with_capacity(text.len());
+    for (i, line) in all_lines.iter().enumerate() {
+        result.push_str(line);
+        if i + 1 < all_lines.len() && !line.ends_with('\n') {
+            result.push_str(terminator);
+        }
+    }
+    result
 }
 
+/// Compares the lines of `left_content` and `right_content` according to
+/// `options` and returns the resulting chunks.
+///
+/// `check` is called before comparing and for each range of lines compared;
+/// an error returned from it stops the comparison.
 fn make_diff_chunks(
     left_content: &str,
     right_content: &str,
-) -> Vec<DiffChunk> {
-    let diff = similar::TextDiff::from_lines(left_content, right_content);
+    options: &TextDiffOptions,
+    mut check: impl FnMut() -> io::Result<()>,
+) -> io::Result<Vec<DiffChunk>> {
+    let left_lines: Vec<&str> = left_content.split_inclusive('\n').collect();
+    let right_lines: Vec<&str> = right_content.split_inclusive('\n').collect();
+    let left_keys: Vec<String> =
+        left_lines.iter().map(|line| options.key(line)).collect();
+    let right_keys: Vec<String> =
+        right_lines.iter().map(|line| options.key(line)).collect();
+    check()?;
+    let ops = similar::capture_diff_slices(
+        similar::Algorithm::Myers,
+        &left_keys,
+        &right_keys,
+    );
     let mut chunks = Vec::new();
 
-    for op in diff.ops() {
-        match op {
-            similar::DiffOp::Equal {
-                old_index,
-                new_index,
-                len,
-            } => {
-                // 1. Equal { old_index, new_index, len }
-                let mut left_lines = Vec::new();
-                let mut right_lines = Vec::new();
-                for i in 0..*len {
-                    let line = diff.iter_changes(op).nth(i).unwrap();
-                    left_lines.push(DiffLine {
-                        line_number: Some(old_index + i + 1),
-                        content: line.value().to_string(),
-                    });
-                    right_lines.push(DiffLine {
-                        line_number: Some(new_index + i + 1),
-                        content: line.value().to_string(),
-                    });
-                }
-                chunks.push(DiffChunk {
+    for op in ops {
+        check()?;
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        let mut left = diff_lines(&left_lines, old_range);
+        let mut right = diff_lines(&right_lines, new_range);
+        let all_blank = || {
+            left.iter()
+                .chain(&right)
+                .all(|line| line.content.trim().is_empty())
+        };
+        if tag == similar::DiffTag::Equal
+            || (options.ignore_blank_lines && all_blank())
+        {
+            let chunk = make_chunk(LineDiffType::Unchanged, left, right);
+            // Ignored blank lines are merged with the unchanged lines around
+            match chunks.last_mut() {
+                Some(DiffChunk {
                     diff_type: LineDiffType::Unchanged,
                     left_lines,
                     right_lines,
-                });
-            }
-            similar::DiffOp::Delete {
-                old_index, old_len, ..
-            } => {
-                // 2. Delete { old_index, old_len, .. }
-                let mut left_lines = Vec::new();
-                let mut right_lines = Vec::new();
-                for i in 0..*old_len {
-                    let line = diff.iter_changes(op).nth(i).unwrap();
-                    left_lines.push(DiffLine {
-                        line_number: Some(old_index + i + 1),
-                        content: line.value().to_string(),
-                    });
-                    right_lines.push(DiffLine {
-                        line_number: None,
-                        content: String::new(),
-                    });
+                }) => {
+                    left_lines.extend(chunk.left_lines);
+                    right_lines.extend(chunk.right_lines);
                 }
-                chunks.push(DiffChunk {
-                    diff_type: LineDiffType::Removed,
-                    left_lines,
-                    right_lines,
-                });
+                _ => chunks.push(chunk),
             }
-            similar::DiffOp::Insert {
-                new_index, new_len, ..
-            } => {
-                // 3. Insert { new_index, new_len, .. }
-                let mut left_lines = Vec::new();
-                let mut right_lines = Vec::new();
-                for i in 0..*new_len {
-                    let line = diff.iter_changes(op).nth(i).unwrap();
-                    left_lines.push(DiffLine {
-                        line_number: None,
-                        content: String::new(),
-                    });
-                    right_lines.push(DiffLine {
-                        line_number: Some(new_index + i + 1),
-                        content: line.value().to_string(),
-                    });
-                }
-                chunks.push(DiffChunk {
-                    diff_type: LineDiffType::Added,
-                    left_lines,
-                    right_lines,
-                });
+            continue;
+        }
+        match tag {
+            similar::DiffTag::Delete => {
+                chunks.push(make_chunk(LineDiffType::Removed, left, right));
             }
-            similar::DiffOp::Replace {
-                old_index,
-                old_len,
-                new_index,
-                new_len,
-            } => {
-                // 4. Replace { old_index, old_len, new_index, new_len }
-                let mut left_lines = Vec::new();
-                let mut right_lines = Vec::new();
-                let common_len = (*old_len).min(*new_len);
-
+            similar::DiffTag::Insert => {
+                chunks.push(make_chunk(LineDiffType::Added, left, right));
+            }
+            _ => {
                 // Map overlapping lines to 'Changed'
-                for i in 0..common_len {
-                    let left_line = diff
-                        .iter_changes(op)
-                        .filter(|c| c.tag() == similar::ChangeTag::Delete)
-                        .nth(i)
-                        .unwrap();
-                    let right_line = diff
-                        .iter_changes(op)
-                        .filter(|c| c.tag() == similar::ChangeTag::Insert)
-                        .nth(i)
-                        .unwrap();
-                    left_lines.push(DiffLine {
-                        line_number: Some(old_index + i + 1),
-                        content: left_line.value().to_string(),
-                    });
-                    right_lines.push(DiffLine {
-                        line_number: Some(new_index + i + 1),
-                        content: right_line.value().to_string(),
-                    });
+                let common_len = left.len().min(right.len());
+                let rem_left = left.split_off(common_len);
+                let rem_right = right.split_off(common_len);
+                for (l, r) in left.iter_mut().zip(&mut right) {
+                    (l.inline_changes, r.inline_changes) =
+                        inline_changes(&l.content, &r.content);
                 }
-                chunks.push(DiffChunk {
-                    diff_type: LineDiffType::Changed,
-                    left_lines,
-                    right_lines,
-                });
+                chunks.push(make_chunk(LineDiffType::Changed, left, right));
 
                 // Handle remaining lines in the Replace op
-                if old_len > new_len {
-                    let mut rem_left = Vec::new();
-                    let mut rem_right = Vec::new();
-                    for i in common_len..*old_len {
-                        let left_line = diff
-                            .iter_changes(op)
-                            .filter(|c| c.tag() == similar::ChangeTag::Delete)
-                            .nth(i)
-                            .unwrap();
-                        rem_left.push(DiffLine {
-                            line_number: Some(old_index + i + 1),
-                            content: left_line.value().to_string(),
-                        });
-                        rem_right.push(DiffLine {
-                            line_number: None,
-                            content: String::new(),
-                        });
-                    }
-                    chunks.push(DiffChunk {
-                        diff_type: LineDiffType::Removed,
-                        left_lines: rem_left,
-                        right_lines: rem_right,
-                    });
-                } else if new_len > old_len {
-                    let mut rem_left = Vec::new();
-                    let mut rem_right = Vec::new();
-                    for i in common_len..*new_len {
-                        let right_line = diff
-                            .iter_changes(op)
-                            .filter(|c| c.tag() == similar::ChangeTag::Insert)
-                            .nth(i)
-                            .unwrap();
-                        rem_left.push(DiffLine {
-                            line_number: None,
-                            content: String::new(),
-                        });
-                        rem_right.push(DiffLine {
-                            line_number: Some(new_index + i + 1),
-                            content: right_line.value().to_string(),
-                        });
-                    }
-                    chunks.push(DiffChunk {
-                        diff_type: LineDiffType::Added,
-                        left_lines: rem_left,
-                        right_lines: rem_right,
-                    });
+                if !rem_left.is_empty() {
+                    chunks.push(make_chunk(
+                        LineDiffType::Removed,
+                        rem_left,
+                        Vec::new(),
+                    ));
+                } else if !rem_right.is_empty() {
+                    chunks.push(make_chunk(
+                        LineDiffType::Added,
+                        Vec::new(),
+                        rem_right,
+                    ));
                 }
             }
         }
     }
-    chunks
+    Ok(chunks)
 }
 
 impl TextDiff {
@@ -241,34 +442,215 @@ impl TextDiff {
         left_file: &Option<FSItem>,
         right_file: &Option<FSItem>,
     ) -> io::Result<Self> {
-        let left_content = if let Some(f) = left_file {
-            fs::read_to_string(f.path())?
-        } else {
-            String::new()
-        };
-        let right_content = if let Some(f) = right_file {
-            fs::read_to_string(f.path())?
-        } else {
-            String::new()
-        };
+        Self::with_options(left_file, right_file, TextDiffOptions::default())
+            .await
+    }
 
-        let chunks = make_diff_chunks(&left_content, &right_content);
+    /// Compares the contents of two text files according to the given
+    /// options.
+    pub async fn with_options(
+        left_file: &Option<FSItem>,
+        right_file: &Option<FSItem>,
+        options: TextDiffOptions,
+    ) -> io::Result<Self> {
+        let cancel = &options.cancel;
+        let (left_encoding, left_text) =
+            read_text(left_file.as_ref(), options.left_encoding, cancel)?;
+        let (right_encoding, right_text) =
+            read_text(right_file.as_ref(), options.right_encoding, cancel)?;
+
+        let chunks = make_diff_chunks(
+            &left_text.content,
+            &right_text.content,
+            &options,
+            || cancel.check(),
+        )?;
 
         Ok(Self {
             left_file: left_file.clone().unwrap_or_default(),
             right_file: right_file.clone().unwrap_or_default(),
+            left_encoding,
+            right_encoding,
             chunks,
+            options,
+            left_text,
+            right_text,
         })
     }
 
-    /// Refreshes the comparison by re-reading the files.
+    /// Refreshes the comparison by re-reading the files, discarding any
+    /// modifications.
+    ///
+    /// If the refresh gets cancelled via `options.cancel`, the comparison
+    /// is left as it was.
     pub async fn refresh(&mut self) -> io::Result<()> {
-        let left_content = fs::read_to_string(self.left_file.path())?;
-        let right_content = fs::read_to_string(self.right_file.path())?;
-        self.chunks = make_diff_chunks(&left_content, &right_content);
+        let left_file =
+            (!self.left_file.name().is_empty()).then_some(&self.left_file);
+        let right_file =
+            (!self.right_file.name().is_empty()).then_some(&self.right_file);
+        let cancel = &self.options.cancel;
+        let (left_encoding, left_text) =
+            read_text(left_file, self.options.left_encoding, cancel)?;
+        let (right_encoding, right_text) =
+            read_text(right_file, self.options.right_encoding, cancel)?;
+        self.chunks = make_diff_chunks(
+            &left_text.content,
+            &right_text.content,
+            &self.options,
+            || cancel.check(),
+        )?;
+        self.left_encoding = left_encoding;
+        self.right_encoding = right_encoding;
+        self.left_text = left_text;
+        self.right_text = right_text;
+        Ok(())
+    }
+
+    /// Returns `true` if the text on `side` has been modified since it was
+    /// read or saved.
+    pub fn is_modified(&self, side: DiffSide) -> bool {
+        self.text(side).modified
+    }
+
+    /// Returns `true` if the file on `side` contained sequences invalid in
+    /// its encoding, which have been replaced in the text.
+    pub fn is_malformed(&self, side: DiffSide) -> bool {
+        self.text(side).malformed
+    }
+
+    /// Replaces the lines of the chunk at `index` on the side opposite to
+    /// `from` by its lines on side `from`, and compares the texts again.
+    ///
+    /// The chunks get renumbered, so indices obtained before are invalid
+    /// afterwards.
+    pub fn copy_chunk(&mut self, index: usize, from: DiffSide) {
+        let to = match from {
+            DiffSide::Left => DiffSide::Right,
+            DiffSide::Right => DiffSide::Left,
+        };
+        let lines: Vec<String> = self.chunks[index]
+            .lines(from)
+            .iter()
+            .filter(|line| line.line_number.is_some())
+            .map(|line| line.content.clone())
+            .collect();
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        self.replace_lines(index, to, &lines);
+    }
+
+    /// Removes the lines of the chunk at `index` on side `side`, and
+    /// compares the texts again.
+    ///
+    /// The chunks get renumbered, so indices obtained before are invalid
+    /// afterwards.
+    pub fn delete_chunk(&mut self, index: usize, side: DiffSide) {
+        self.replace_lines(index, side, &[]);
+    }
+
+    /// Writes the text on `side` back to its file, encoded in the file's
+    /// encoding.
+    ///
+    /// The text is written to a temporary file, which then replaces the
+    /// file atomically, keeping its permissions.
+    ///
+    /// Fails if there is no file on that side, if the file contained
+    /// sequences invalid in its encoding, which would get lost, or if the
+    /// text can't be represented in the encoding.
+    pub async fn save(&mut self, side: DiffSide) -> io::Result<()> {
+        let (file, encoding) = match side {
+            DiffSide::Left => (&self.left_file, self.left_encoding),
+            DiffSide::Right => (&self.right_file, self.right_encoding),
+        };
+        if file.name().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no file to save the text to",
+            ));
+        }
+        let text = self.text(side);
+        if text.malformed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is not valid {}; saving it would replace the invalid \
+                     content",
+                    file.path().display(),
+                    encoding.encoding.name()
+                ),
+            ));
+        }
+        let content = encoding.encode(&text.content)?;
+        // Replace the file a link points to, not the link
+        let path = tokio::fs::canonicalize(file.path())
+            .await
+            .unwrap_or_else(|_| file.path().clone());
+        let (tmp, mut writer) = create_temp_file(&path).await?;
+        let result = async {
+            writer.write_all(&content).await?;
+            writer.sync_all().await?;
+            drop(writer);
+            if let Ok(metadata) = tokio::fs::metadata(&path).await {
+                tokio::fs::set_permissions(&tmp, metadata.permissions())
+                    .await?;
+            }
+            tokio::fs::rename(&tmp, &path).await
+        }
+        .await;
+        if let Err(err) = result {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(err);
+        }
+        sync_dir(&path).await?;
+        self.text_mut(side).modified = false;
         Ok(())
     }
 
+    /// Replaces the lines of the chunk at `index` on `side` by `lines`, and
+    /// compares the texts again.
+    fn replace_lines(&mut self, index: usize, side: DiffSide, lines: &[&str]) {
+        let count = |chunk: &DiffChunk| {
+            chunk
+                .lines(side)
+                .iter()
+                .filter(|line| line.line_number.is_some())
+                .count()
+        };
+        let start: usize = self.chunks[..index].iter().map(count).sum();
+        let range = start..start + count(&self.chunks[index]);
+        let text = self.text_mut(side);
+        text.content = splice_lines(&text.content, range, lines);
+        text.modified = true;
+        self.recompute();
+    }
+
+    /// Compares the current texts again.
+    fn recompute(&mut self) {
+        // Edits are not cancellable, so comparing can't fail
+        self.chunks = make_diff_chunks(
+            &self.left_text.content,
+            &self.right_text.content,
+            &self.options,
+            || Ok(()),
+        )
+        .unwrap_or_default();
+    }
+
+    /// Returns the text on `side`.
+    fn text(&self, side: DiffSide) -> &SideText {
+        match side {
+            DiffSide::Left => &self.left_text,
+            DiffSide::Right => &self.right_text,
+        }
+    }
+
+    /// Returns the text on `side`.
+    fn text_mut(&mut self, side: DiffSide) -> &mut SideText {
+        match side {
+            DiffSide::Left => &mut self.left_text,
+            DiffSide::Right => &mut self.right_text,
+        }
+    }
+
     pub fn name(&self) -> &ffi::OsString {
         match &self.left_file.name().is_empty() {
             false => self.left_file.name(),
@@ -285,9 +667,11 @@ mod tests {
 
     use super::*;
 
-    async fn create_test_file(content: &str) -> (NamedTempFile, FSItem) {
+    async fn create_test_file(
+        content: impl AsRef<[u8]>,
+    ) -> (NamedTempFile, FSItem) {
         let mut file = NamedTempFile::new().unwrap();
-        file.write_all(content.as_bytes()).unwrap();
+        file.write_all(content.as_ref()).unwrap();
         let path = file.path().to_path_buf();
         let fs_item = FSItem::new(path).await;
         (file, fs_item)
@@ -357,4 +741,243 @@ mod tests {
         assert_eq!(diff.chunks[2].left_lines[0].line_number, None);
         assert_eq!(diff.chunks[2].right_lines[0].content, "line4\n");
     }
+
+    #[tokio::test]
+    async fn test_file_diff_inline_changes() {
+        let left_content = "fn main() {\n    let x = 1;\n}\n";
+        let right_content = "fn main() {\n    let y = 1;\n}\n";
+
+        let (_l_file, _l_item) = create_test_file(left_content).await;
+        let (_r_file, _r_item) = create_test_file(right_content).await;
+
+        let diff =
+            TextDiff::new(&Some(_l_item), &Some(_r_item)).await.unwrap();
+
+        assert_eq!(diff.chunks.len(), 3);
+        assert!(diff.chunks[0].left_lines[0].inline_changes.is_empty());
+
+        let chunk = &diff.chunks[1];
+        assert_eq!(chunk.diff_type, LineDiffType::Changed);
+        let (left, right) = (&chunk.left_lines[0], &chunk.right_lines[0]);
+        assert_eq!(left.content, "    let x = 1;\n");
+        assert_eq!(right.content, "    let y = 1;\n");
+        let changed = |line: &DiffLine| -> Vec<String> {
+            line.inline_changes
+                .iter()
+                .map(|range| line.content[range.clone()].to_string())
+                .collect()
+        };
+        assert_eq!(changed(left), ["x"]);
+        assert_eq!(changed(right), ["y"]);
+    }
+
+    #[tokio::test]
+    async fn test_file_diff_options() {
+        let left_content = "if x {\r\n\treturn  1;  \r\n}\r\n";
+        let right_content = "IF X {\n    return 1;\n\n}";
+
+        let (_l_file, _l_item) = create_test_file(left_content).await;
+        let (_r_file, _r_item) = create_test_file(right_content).await;
+        let diff_types = async |options: TextDiffOptions| {
+            TextDiff::with_options(
+                &Some(_l_item.clone()),
+                &Some(_r_item.clone()),
+                options,
+            )
+            .await
+            .unwrap()
+            .chunks
+            .iter()
+            .map(|chunk| chunk.diff_type)
+            .collect::<Vec<_>>()
+        };
+
+        let mut options = TextDiffOptions::default();
+        assert_eq!(
+            diff_types(options.clone()).await,
+            [LineDiffType::Changed, LineDiffType::Added]
+        );
+
+        options.normalize_line_endings = true;
+        options.ignore_case = true;
+        options.whitespace = WhitespaceMode::IgnoreAmount;
+        assert_eq!(
+            diff_types(options.clone()).await,
+            [
+                LineDiffType::Unchanged,
+                LineDiffType::Added,
+                LineDiffType::Unchanged
+            ]
+        );
+
+        options.ignore_blank_lines = true;
+        let diff =
+            TextDiff::with_options(&Some(_l_item), &Some(_r_item), options)
+                .await
+                .unwrap();
+        assert_eq!(diff.chunks.len(), 1);
+        assert_eq!(diff.chunks[0].diff_type, LineDiffType::Unchanged);
+        // The original content is kept
+        assert_eq!(diff.chunks[0].left_lines[1].content, "\treturn  1;  \r\n");
+        assert_eq!(diff.chunks[0].right_lines[1].content, "    return 1;\n");
+    }
+
+    #[test]
+    fn test_whitespace_modes() {
+        let key = |whitespace| {
+            TextDiffOptions {
+                whitespace,
+                ..Default::default()
+            }
+            .key(" a \t b  \n")
+        };
+        assert_eq!(key(WhitespaceMode::Exact), " a \t b  \n");
+        assert_eq!(key(WhitespaceMode::IgnoreTrailing), " a \t b\n");
+        assert_eq!(key(WhitespaceMode::IgnoreAmount), " a b\n");
+        assert_eq!(key(WhitespaceMode::IgnoreAll), "ab\n");
+    }
+
+    #[tokio::test]
+    async fn test_file_diff_encodings() {
+        let left_content = b"Gr\xfc\xdfe aus K\xf6ln\nund M\xfcnchen\n";
+        let right_content = "\u{feff}Grüße aus Köln\nund München\n";
+
+        let (_l_file, _l_item) = create_test_file(left_content).await;
+        let (_r_file, _r_item) = create_test_file(right_content).await;
+
+        let diff =
+            TextDiff::new(&Some(_l_item.clone()), &Some(_r_item.clone()))
+                .await
+                .unwrap();
+        assert_eq!(diff.left_encoding.to_string(), "windows-1252");
+        assert_eq!(diff.right_encoding.to_string(), "UTF-8 BOM");
+        assert_eq!(diff.chunks.len(), 1);
+        assert_eq!(diff.chunks[0].diff_type, LineDiffType::Unchanged);
+        assert_eq!(diff.chunks[0].left_lines[0].content, "Grüße aus Köln\n");
+
+        // A wrong encoding given makes the lines differ
+        let options = TextDiffOptions {
+            right_encoding: Some(encoding_rs::WINDOWS_1252),
+            ..Default::default()
+        };
+        let diff =
+            TextDiff::with_options(&Some(_l_item), &Some(_r_item), options)
+                .await
+                .unwrap();
+        assert!(!diff.right_encoding.bom);
+        assert_eq!(diff.chunks[0].diff_type, LineDiffType::Changed);
+    }
+
+    #[test]
+    fn test_splice_lines() {
+        assert_eq!(splice_lines("a\nb\n", 1..2, &["c\n", "d\n"]), "a\nc\nd\n");
+        assert_eq!(splice_lines("a\nb\n", 0..1, &[]), "b\n");
+        // Lines getting followed by others are terminated
+        assert_eq!(splice_lines("x", 1..1, &["y\n"]), "x\ny\n");
+        assert_eq!(splice_lines("a\r\nb", 1..1, &["c"]), "a\r\nc\r\nb");
+    }
+
+    #[tokio::test]
+    async fn test_edit_chunks() {
+        let (_l_file, _l_item) = create_test_file("a\nb\nc\n").await;
+        let (_r_file, _r_item) = create_test_file("a\nB\nc\nd").await;
+
+        let mut diff =
+            TextDiff::new(&Some(_l_item.clone()), &Some(_r_item.clone()))
+                .await
+                .unwrap();
+        let diff_types = |diff: &TextDiff| {
+            diff.chunks
+                .iter()
+                .map(|chunk| chunk.diff_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            diff_types(&diff),
+            [
+                LineDiffType::Unchanged,
+                LineDiffType::Changed,
+                LineDiffType::Unchanged,
+                LineDiffType::Added
+            ]
+        );
+
+        diff.copy_chunk(1, DiffSide::Left);
+        assert!(diff.is_modified(DiffSide::Right));
+        assert!(!diff.is_modified(DiffSide::Left));
+        assert_eq!(
+            diff_types(&diff),
+            [LineDiffType::Unchanged, LineDiffType::Added]
+        );
+
+        diff.copy_chunk(1, DiffSide::Right);
+        assert!(diff.is_modified(DiffSide::Left));
+        assert_eq!(diff_types(&diff), [LineDiffType::Unchanged]);
+
+        diff.delete_chunk(0, DiffSide::Left);
+        assert_eq!(diff_types(&diff), [LineDiffType::Added]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = fs::Permissions::from_mode(0o640);
+            fs::set_permissions(_r_item.path(), permissions).unwrap();
+        }
+        diff.save(DiffSide::Right).await.unwrap();
+        assert!(!diff.is_modified(DiffSide::Right));
+        assert_eq!(fs::read_to_string(_r_item.path()).unwrap(), "a\nb\nc\nd");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = fs::metadata(_r_item.path()).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        }
+        diff.refresh().await.unwrap();
+        assert!(!diff.is_modified(DiffSide::Left));
+        assert_eq!(
+            diff_types(&diff),
+            [LineDiffType::Unchanged, LineDiffType::Added]
+        );
+
+        // Content invalid in the encoding of the file is not overwritten
+        let (_m_file, _m_item) = create_test_file(b"a\xff\n").await;
+        let options = TextDiffOptions {
+            right_encoding: Some(encoding_rs::UTF_8),
+            ..Default::default()
+        };
+        let mut diff =
+            TextDiff::with_options(&Some(_l_item), &Some(_m_item), options)
+                .await
+                .unwrap();
+        diff.copy_chunk(0, DiffSide::Left);
+        let err = diff.save(DiffSide::Right).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_diff() {
+        let (_l_file, _l_item) = create_test_file("a\nb\n").await;
+        let (_r_file, _r_item) = create_test_file("a\nc\n").await;
+        let options = TextDiffOptions::default();
+        let cancel = options.cancel.clone();
+        let mut diff =
+            TextDiff::with_options(&Some(_l_item), &Some(_r_item), options)
+                .await
+                .unwrap();
+        diff.delete_chunk(1, DiffSide::Right);
+        cancel.cancel();
+        let err = diff.refresh().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        // The comparison is left as it was
+        assert!(diff.is_modified(DiffSide::Right));
+        assert_eq!(diff.chunks.len(), 2);
+        // Edits are still possible
+        diff.copy_chunk(1, DiffSide::Left);
+        assert_eq!(diff.chunks.len(), 1);
+        let err =
+            TextDiff::with_options(&Some(diff.left_file), &None, diff.options)
+                .await
+                .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
 }
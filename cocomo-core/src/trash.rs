@@ -0,0 +1,299 @@
+// ---------------------------------------------------------------------------
+// Copyright:   (c) 2026 ff. Michael Amrhein (michael@adrhinum.de)
+// License:     This program is part of a larger application. For license
+//              details please read the file LICENSE.TXT provided together
+//              with the application.
+// ---------------------------------------------------------------------------
+// $Source$
+// $Revision$
+
+//! # Trash Module (`trash`)
+//!
+//! This internal module implements moving files and directories to the
+//! trash as described in the freedesktop.org Trash specification.
+//!
+//! Items residing on the same file system as the home trash
+//! (`$XDG_DATA_HOME/Trash`) are moved there. Items on other file systems are
+//! moved to the trash directory at the top of their mount point, which is
+//! `$topdir/.Trash/$uid`, if the administrator provided a suitable
+//! `$topdir/.Trash`, or `$topdir/.Trash-$uid` otherwise. For each trashed
+//! item a `.trashinfo` file recording its original path and the time of
+//! deletion is written, so that it can be restored by any compliant file
+//! manager.
+
+use std::{
+    env,
+    fs::Metadata,
+    io,
+    path::{self, Path, PathBuf},
+};
+
+use chrono::Local;
+use tokio::fs;
+
+use crate::fsops::{device, normalize, numbered_name};
+
+/// Returns the home trash directory, `$XDG_DATA_HOME/Trash`, falling back to
+/// `$HOME/.local/share/Trash`.
+pub(crate) fn home_trash() -> io::Result<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(data_home) if Path::new(&data_home).is_absolute() => {
+            Ok(PathBuf::from(data_home).join("Trash"))
+        }
+        _ => env::home_dir()
+            .map(|home| home.join(".local/share/Trash"))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "home directory not found",
+                )
+            }),
+    }
+}
+
+/// Returns the trash directory to be used for the item at `path`, which
+/// must exist, given the home trash directory `home_trash`.
+///
+/// Missing trash directories are created.
+pub(crate) async fn trash_dir(
+    path: &Path,
+    home_trash: &Path,
+) -> io::Result<PathBuf> {
+    let dev = device(&fs::symlink_metadata(path).await?);
+    if fs::create_dir_all(home_trash).await.is_ok()
+        && device(&fs::metadata(home_trash).await?) == dev
+    {
+        return Ok(home_trash.to_path_buf());
+    }
+    let top_dir = top_dir(path, dev).await?;
+    let uid = uid().to_string();
+    let admin_trash = top_dir.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&admin_trash).await
+        && metadata.is_dir()
+        && is_sticky(&metadata)
+    {
+        let user_trash = admin_trash.join(&uid);
+        if create_private_dir(&user_trash).await.is_ok() {
+            return Ok(user_trash);
+        }
+    }
+    let user_trash = top_dir.join(format!(".Trash-{uid}"));
+    create_private_dir(&user_trash).await?;
+    Ok(user_trash)
+}
+
+/// Returns the top directory of the mount point holding the item at `path`
+/// on the device `dev`.
+pub(crate) async fn top_dir(path: &Path, dev: u64) -> io::Result<PathBuf> {
+    let path = normalize(&path::absolute(path)?);
+    let mut top_dir = path.parent().unwrap_or(&path).to_path_buf();
+    if device(&fs::metadata(&top_dir).await?) != dev {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cannot move a mount point to the trash",
+        ));
+    }
+    while let Some(parent) = top_dir.parent() {
+        if device(&fs::metadata(parent).await?) != dev {
+            break;
+        }
+        top_dir = parent.to_path_buf();
+    }
+    Ok(top_dir)
+}
+
+/// Creates the directory `path`, accessible by the current user only,
+/// unless it already exists, and checks that it is a real directory.
+pub(crate) async fn create_private_dir(path: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(0o700);
+    match builder.create(path).await {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => {
+            return Err(err);
+        }
+        _ => {}
+    }
+    if fs::symlink_metadata(path).await?.is_dir() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("not a directory: {}", path.display()),
+        ))
+    }
+}
+
+/// Moves the item at `path` to the trash directory `trash_dir` and returns
+/// its new path.
+pub(crate) async fn move_to_trash(
+    path: &Path,
+    trash_dir: &Path,
+) -> io::Result<PathBuf> {
+    let abs_path = normalize(&path::absolute(path)?);
+    let name = abs_path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "item has no name")
+    })?;
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    create_private_dir(&files_dir).await?;
+    create_private_dir(&info_dir).await?;
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(&abs_path),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    let mut n = 1_u32;
+    loop {
+        let trash_name = if n == 1 {
+            name.to_os_string()
+        } else {
+            numbered_name(name, n, true)
+        };
+        n += 1;
+        let mut info_name = trash_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = info_dir.join(info_name);
+        // Creating the info file atomically reserves the name
+        match write_new(&info_path, &info).await {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                continue;
+            }
+            result => result?,
+        }
+        let trashed = files_dir.join(&trash_name);
+        if fs::symlink_metadata(&trashed).await.is_ok() {
+            // Left over without info file; keep it and try the next name
+            fs::remove_file(&info_path).await?;
+            continue;
+        }
+        if let Err(err) = fs::rename(path, &trashed).await {
+            let _ = fs::remove_file(&info_path).await;
+            return Err(err);
+        }
+        return Ok(trashed);
+    }
+}
+
+/// Moves the item `trashed` in a trash directory back to `path` and removes
+/// its `.trashinfo` file.
+pub(crate) async fn restore(trashed: &Path, path: &Path) -> io::Result<()> {
+    fs::rename(trashed, path).await?;
+    if let (Some(files_dir), Some(name)) =
+        (trashed.parent(), trashed.file_name())
+        && let Some(trash_dir) = files_dir.parent()
+    {
+        let mut info_name = name.to_os_string();
+        info_name.push(".trashinfo");
+        fs::remove_file(trash_dir.join("info").join(info_name)).await?;
+    }
+    Ok(())
+}
+
+/// Writes `content` to the file `path`, which must not exist yet.
+async fn write_new(path: &Path, content: &str) -> io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await?;
+    file.write_all(content.as_bytes()).await?;
+    file.sync_all().await
+}
+
+/// Returns `path` escaped like the path of an URL, as required for the
+/// `Path` key of a `.trashinfo` file.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Returns `true` if the sticky bit is set in `metadata`.
+#[cfg(unix)]
+fn is_sticky(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o1000 != 0
+}
+
+#[cfg(not(unix))]
+fn is_sticky(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Returns the id of the current user.
+#[cfg(unix)]
+pub(crate) fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail.
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn uid() -> u32 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(
+            encode_path(Path::new("/tmp/a b/ü%.txt")),
+            "/tmp/a%20b/%C3%BC%25.txt"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_to_trash() -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = tempdir()?;
+        let tmp_dir = tmp.path();
+        let home_trash = tmp_dir.join("data").join("Trash");
+        let file = tmp_dir.join("a.txt");
+        let dir = tmp_dir.join("dir");
+        fs::write(&file, "aaa").await?;
+        fs::create_dir(&dir).await?;
+        fs::write(dir.join("b.txt"), "bb").await?;
+        // The temporary directory is on the same file system as itself
+        let trash = trash_dir(&file, &home_trash).await?;
+        assert_eq!(trash, home_trash);
+        let trashed = move_to_trash(&file, &trash).await?;
+        assert_eq!(trashed, trash.join("files").join("a.txt"));
+        assert!(!file.exists());
+        assert_eq!(fs::read_to_string(&trashed).await?, "aaa");
+        let info =
+            fs::read_to_string(trash.join("info").join("a.txt.trashinfo"))
+                .await?;
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        assert_eq!(
+            lines.next().map(String::from),
+            Some(format!("Path={}", encode_path(&file)))
+        );
+        assert!(lines.next().is_some_and(|l| l.starts_with("DeletionDate=")));
+        // Names already taken in the trash get numbered
+        fs::write(&file, "new").await?;
+        let trashed = move_to_trash(&file, &trash).await?;
+        assert_eq!(trashed, trash.join("files").join("a (2).txt"));
+        assert!(trash.join("info").join("a (2).txt.trashinfo").exists());
+        // Directories are trashed as a whole
+        let trashed = move_to_trash(&dir, &trash).await?;
+        assert!(!dir.exists());
+        assert_eq!(fs::read_to_string(trashed.join("b.txt")).await?, "bb");
+        restore(&trashed, &dir).await?;
+        assert!(dir.join("b.txt").exists());
+        assert!(!trash.join("info").join("dir.trashinfo").exists());
+        Ok(())
+    }
+}
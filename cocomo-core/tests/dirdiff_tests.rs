@@ -7,10 +7,13 @@
 // $Source$
 // $Revision$
 
-use std::fs;
+use std::{fs, time::Duration};
 
-use cocomo_core::{By, DiffItemType, DiffSide, DirDiff, FSItem};
-use filetime::{set_file_mtime, FileTime};
+use cocomo_core::{
+    AttrDiff, By, DiffItemType, DiffOptions, DiffSide, DirDiff, FSItem,
+    ItemFilter, LinkMode, ScanEvent,
+};
+use filetime::{FileTime, set_file_mtime};
 
 async fn setup_test_dirs() -> (tempfile::TempDir, tempfile::TempDir) {
     let left_dir = tempfile::tempdir().unwrap();
@@ -138,3 +141,527 @@ async fn test_dirdiff_all_variants() {
     assert!(found_right_newer, "Right newer not found");
     assert!(found_diff_no_newer, "Different (no newer) not found");
 }
+
+#[tokio::test]
+async fn test_dirdiff_by_content() {
+    let (left_dir, right_dir) = setup_test_dirs().await;
+    let left_path = left_dir.path();
+    let right_path = right_dir.path();
+
+    // Same content, but different mtime
+    let left_touched = left_path.join("touched.txt");
+    let right_touched = right_path.join("touched.txt");
+    fs::write(&left_touched, "same content").unwrap();
+    fs::write(&right_touched, "same content").unwrap();
+    set_file_mtime(&left_touched, FileTime::from_unix_time(2000000, 0))
+        .unwrap();
+    set_file_mtime(&right_touched, FileTime::from_unix_time(1000000, 0))
+        .unwrap();
+
+    // Same mtime and size, but different content
+    let left_changed = left_path.join("changed.txt");
+    let right_changed = right_path.join("changed.txt");
+    fs::write(&left_changed, "content A").unwrap();
+    fs::write(&right_changed, "content B").unwrap();
+    let now = FileTime::from_unix_time(1000000, 0);
+    set_file_mtime(&left_changed, now).unwrap();
+    set_file_mtime(&right_changed, now).unwrap();
+
+    let left_fsitem = FSItem::new(left_path).await;
+    let right_fsitem = FSItem::new(right_path).await;
+    let options = DiffOptions {
+        by_content: true,
+        ..Default::default()
+    };
+    let diff = DirDiff::with_options(
+        &Some(left_fsitem),
+        &Some(right_fsitem),
+        options,
+    )
+    .await
+    .unwrap();
+
+    for item in &diff.items {
+        let name = item.name().to_str().unwrap();
+        let expected = match name {
+            "left_only.txt" => DiffItemType::LeftOnly,
+            "right_only.txt" => DiffItemType::RightOnly,
+            "same.txt" | "touched.txt" => {
+                DiffItemType::Same { by: By::Content }
+            }
+            "diff_left_newer.txt" => DiffItemType::Different {
+                newer: Some(DiffSide::Left),
+            },
+            "diff_right_newer.txt" => DiffItemType::Different {
+                newer: Some(DiffSide::Right),
+            },
+            "diff_size.txt" | "changed.txt" => {
+                DiffItemType::Different { newer: None }
+            }
+            _ => panic!("Unexpected item: {name}"),
+        };
+        assert_eq!(item.diff_item_type, expected, "{name}");
+    }
+    assert_eq!(diff.items.len(), 8);
+}
+
+#[tokio::test]
+async fn test_dirdiff_recursive() {
+    let left_dir = tempfile::tempdir().unwrap();
+    let right_dir = tempfile::tempdir().unwrap();
+    let left_path = left_dir.path();
+    let right_path = right_dir.path();
+    let now = FileTime::from_unix_time(1000000, 0);
+
+    // Subtree with identical content
+    for base in [left_path, right_path] {
+        fs::create_dir_all(base.join("same").join("sub")).unwrap();
+        let file = base.join("same").join("sub").join("file.txt");
+        fs::write(&file, "same content").unwrap();
+        set_file_mtime(&file, now).unwrap();
+    }
+
+    // Subtree with a difference two levels below
+    for (base, content) in [(left_path, "content A"), (right_path, "content")]
+    {
+        fs::create_dir_all(base.join("diff").join("sub")).unwrap();
+        let file = base.join("diff").join("sub").join("file.txt");
+        fs::write(&file, content).unwrap();
+        set_file_mtime(&file, now).unwrap();
+    }
+
+    // Subtree only on the left side
+    fs::create_dir_all(left_path.join("left").join("sub")).unwrap();
+    fs::write(left_path.join("left").join("sub").join("file.txt"), "left")
+        .unwrap();
+
+    let left_fsitem = FSItem::new(left_path).await;
+    let right_fsitem = FSItem::new(right_path).await;
+    let options = DiffOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    let diff = DirDiff::with_options(
+        &Some(left_fsitem),
+        &Some(right_fsitem),
+        options,
+    )
+    .await
+    .unwrap();
+    assert_eq!(diff.items.len(), 3);
+    assert_eq!(diff.status(), DiffItemType::Different { newer: None });
+
+    for item in &diff.items {
+        let sub_diff = item.sub_diff.as_ref().expect("Sub diff missing");
+        let sub_item = &sub_diff.items[0];
+        assert_eq!(sub_item.name(), "sub");
+        match item.name().to_str().unwrap() {
+            "same" => {
+                assert_eq!(
+                    item.diff_item_type,
+                    DiffItemType::Same { by: By::Metadata }
+                );
+                assert_eq!(sub_item.diff_item_type, item.diff_item_type);
+            }
+            "diff" => {
+                assert_eq!(
+                    item.diff_item_type,
+                    DiffItemType::Different { newer: None }
+                );
+                assert_eq!(sub_item.diff_item_type, item.diff_item_type);
+            }
+            "left" => {
+                assert_eq!(item.diff_item_type, DiffItemType::LeftOnly);
+                assert_eq!(sub_item.diff_item_type, DiffItemType::LeftOnly);
+                let file_item = &sub_item.sub_diff.as_ref().unwrap().items[0];
+                assert_eq!(file_item.diff_item_type, DiffItemType::LeftOnly);
+            }
+            name => panic!("Unexpected item: {name}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dirdiff_filtered() {
+    let (left_dir, right_dir) = setup_test_dirs().await;
+    fs::create_dir(left_dir.path().join("target")).unwrap();
+    fs::write(right_dir.path().join("main.o"), "").unwrap();
+
+    let left_fsitem = FSItem::new(left_dir.path()).await;
+    let right_fsitem = FSItem::new(right_dir.path()).await;
+    let options = DiffOptions {
+        filter: ItemFilter::new(&["target/", "*.o", "left_*"], false).unwrap(),
+        ..Default::default()
+    };
+    let diff = DirDiff::with_options(
+        &Some(left_fsitem),
+        &Some(right_fsitem),
+        options,
+    )
+    .await
+    .unwrap();
+    let names: Vec<_> = diff
+        .items
+        .iter()
+        .map(|item| item.name().to_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "diff_left_newer.txt",
+            "diff_right_newer.txt",
+            "diff_size.txt",
+            "right_only.txt",
+            "same.txt"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_dirdiff_mtime_options() {
+    let left_dir = tempfile::tempdir().unwrap();
+    let right_dir = tempfile::tempdir().unwrap();
+    let left_path = left_dir.path();
+    let right_path = right_dir.path();
+
+    // name, left mtime, right mtime, left content, right content
+    let cases = [
+        ("fat.txt", 1000000, 1000001, "content", "content"),
+        ("dst.txt", 1000000, 1003601, "content", "content"),
+        ("tz.txt", 1000000, 1007200, "content", "content"),
+        ("newer.txt", 1000000, 1000010, "content", "content"),
+        ("changed.txt", 1000000, 1000001, "content A", "content B"),
+    ];
+    for (name, left_mtime, right_mtime, left_content, right_content) in cases {
+        let left_file = left_path.join(name);
+        let right_file = right_path.join(name);
+        fs::write(&left_file, left_content).unwrap();
+        fs::write(&right_file, right_content).unwrap();
+        set_file_mtime(&left_file, FileTime::from_unix_time(left_mtime, 0))
+            .unwrap();
+        set_file_mtime(&right_file, FileTime::from_unix_time(right_mtime, 0))
+            .unwrap();
+    }
+
+    let left_fsitem = Some(FSItem::new(left_path).await);
+    let right_fsitem = Some(FSItem::new(right_path).await);
+    let diff_types = async |options: DiffOptions| {
+        DirDiff::with_options(&left_fsitem, &right_fsitem, options)
+            .await
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| item.diff_item_type)
+            .collect::<Vec<_>>()
+    };
+    let same_by_metadata = DiffItemType::Same { by: By::Metadata };
+    let same_by_content = DiffItemType::Same { by: By::Content };
+    let right_newer = DiffItemType::Different {
+        newer: Some(DiffSide::Right),
+    };
+    let different = DiffItemType::Different { newer: None };
+
+    // Items are sorted by name: changed, dst, fat, newer, tz
+    let options = DiffOptions {
+        mtime_tolerance: Duration::from_secs(2),
+        ..Default::default()
+    };
+    assert_eq!(
+        diff_types(options.clone()).await,
+        [
+            same_by_metadata,
+            right_newer,
+            same_by_metadata,
+            right_newer,
+            right_newer
+        ]
+    );
+    let options = DiffOptions {
+        max_hour_offset: 1,
+        ..options
+    };
+    assert_eq!(
+        diff_types(options.clone()).await,
+        [
+            same_by_metadata,
+            same_by_metadata,
+            same_by_metadata,
+            right_newer,
+            right_newer
+        ]
+    );
+    let options = DiffOptions {
+        max_hour_offset: 2,
+        ..options
+    };
+    let expected = [
+        same_by_metadata,
+        same_by_metadata,
+        same_by_metadata,
+        right_newer,
+        same_by_metadata,
+    ];
+    assert_eq!(diff_types(options.clone()).await, expected);
+    // The number of hours doesn't affect the time taken
+    let options = DiffOptions {
+        max_hour_offset: u32::MAX,
+        ..options
+    };
+    assert_eq!(diff_types(options).await, expected);
+    let options = DiffOptions {
+        ignore_mtime: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        diff_types(options).await,
+        [
+            different,
+            same_by_content,
+            same_by_content,
+            same_by_content,
+            same_by_content
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_dirdiff_progress() {
+    let (left_dir, right_dir) = setup_test_dirs().await;
+    fs::create_dir(left_dir.path().join("sub")).unwrap();
+    fs::create_dir(right_dir.path().join("sub")).unwrap();
+
+    let left_fsitem = FSItem::new(left_dir.path()).await;
+    let right_fsitem = FSItem::new(right_dir.path()).await;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let options = DiffOptions {
+        by_content: true,
+        recursive: true,
+        parallelism: 2,
+        progress: Some(sender),
+        ..Default::default()
+    };
+    let diff = DirDiff::with_options(
+        &Some(left_fsitem),
+        &Some(right_fsitem),
+        options,
+    )
+    .await
+    .unwrap();
+
+    let mut scanned = 0;
+    let mut read = 0;
+    let mut compared = Vec::new();
+    while let Ok(event) = receiver.try_recv() {
+        match event {
+            ScanEvent::Scanned(_) => scanned += 1,
+            ScanEvent::Read(n) => read += n,
+            ScanEvent::Compared(dir) => compared.push(dir),
+        }
+    }
+    // 5 files + 1 dir on each side
+    assert_eq!(scanned, 12);
+    // same.txt and diff_left_newer.txt / diff_right_newer.txt differ in
+    // size, so only same.txt has been read
+    assert_eq!(read, 2 * "same content".len() as u64);
+    // Subdirectory is finished before its parent
+    assert_eq!(
+        compared,
+        [left_dir.path().join("sub"), left_dir.path().to_path_buf()]
+    );
+    assert_eq!(diff.items.len(), 7);
+}
+
+#[tokio::test]
+async fn test_dirdiff_renames() {
+    let left_dir = tempfile::tempdir().unwrap();
+    let right_dir = tempfile::tempdir().unwrap();
+    let left_path = left_dir.path();
+    let right_path = right_dir.path();
+    fs::create_dir(left_path.join("sub")).unwrap();
+    fs::create_dir(right_path.join("sub")).unwrap();
+    // Renamed in the same directory
+    fs::write(left_path.join("old.txt"), "renamed content").unwrap();
+    fs::write(right_path.join("new.txt"), "renamed content").unwrap();
+    // Moved to another directory
+    fs::write(left_path.join("moved.txt"), "moved content").unwrap();
+    fs::write(right_path.join("sub").join("moved.txt"), "moved content")
+        .unwrap();
+    // Renamed and edited
+    let lines: String = (0..20).map(|i| format!("line {i}\n")).collect();
+    fs::write(left_path.join("edited.txt"), &lines).unwrap();
+    fs::write(right_path.join("changed.txt"), lines + "line 20\n").unwrap();
+
+    let left_fsitem = Some(FSItem::new(left_path).await);
+    let right_fsitem = Some(FSItem::new(right_path).await);
+    let options = DiffOptions {
+        recursive: true,
+        detect_renames: true,
+        ..Default::default()
+    };
+    let diff =
+        DirDiff::with_options(&left_fsitem, &right_fsitem, options.clone())
+            .await
+            .unwrap();
+    let renamed = DiffItemType::Renamed { identical: true };
+    let names = |item: &cocomo_core::DiffItem| {
+        (
+            item.left_item.as_ref().map(|item| item.name().clone()),
+            item.right_item.as_ref().map(|item| item.name().clone()),
+        )
+    };
+    // Items are sorted by name: sub/, changed, edited, moved, old
+    let types: Vec<_> =
+        diff.items.iter().map(|item| item.diff_item_type).collect();
+    assert_eq!(
+        types,
+        [
+            DiffItemType::Different { newer: None },
+            DiffItemType::RightOnly,
+            DiffItemType::LeftOnly,
+            renamed,
+            renamed
+        ]
+    );
+    assert_eq!(
+        names(&diff.items[4]),
+        (Some("old.txt".into()), Some("new.txt".into()))
+    );
+    // A moved file is listed in both directories
+    let sub_diff = diff.items[0].sub_diff.as_ref().unwrap();
+    assert_eq!(sub_diff.items[0].diff_item_type, renamed);
+    assert_eq!(
+        sub_diff.items[0].left_item.as_ref().unwrap().path(),
+        &left_path.join("moved.txt")
+    );
+    assert_eq!(
+        diff.items[3].right_item.as_ref().unwrap().path(),
+        &right_path.join("sub").join("moved.txt")
+    );
+
+    // Similar text files are paired as well
+    let options = DiffOptions {
+        rename_similarity: 0.9,
+        ..options
+    };
+    let diff = DirDiff::with_options(&left_fsitem, &right_fsitem, options)
+        .await
+        .unwrap();
+    assert_eq!(diff.items.len(), 4);
+    assert_eq!(
+        diff.items[1].diff_item_type,
+        DiffItemType::Renamed { identical: false }
+    );
+    assert_eq!(
+        names(&diff.items[1]),
+        (Some("edited.txt".into()), Some("changed.txt".into()))
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_dirdiff_attributes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let left_dir = tempfile::tempdir().unwrap();
+    let right_dir = tempfile::tempdir().unwrap();
+    let left_path = left_dir.path();
+    let right_path = right_dir.path();
+    for path in [left_path, right_path] {
+        fs::create_dir(path.join("bin")).unwrap();
+        let script = path.join("bin").join("run.sh");
+        fs::write(&script, "echo").unwrap();
+        set_file_mtime(&script, FileTime::from_unix_time(1000000, 0)).unwrap();
+    }
+    fs::set_permissions(
+        right_path.join("bin").join("run.sh"),
+        fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+
+    let left_fsitem = Some(FSItem::new(left_path).await);
+    let right_fsitem = Some(FSItem::new(right_path).await);
+    let options = DiffOptions {
+        recursive: true,
+        compare_attributes: true,
+        ..Default::default()
+    };
+    let diff = DirDiff::with_options(&left_fsitem, &right_fsitem, options)
+        .await
+        .unwrap();
+    let bin = &diff.items[0];
+    // The content is the same, only the attributes differ
+    assert_eq!(bin.diff_item_type, DiffItemType::Same { by: By::Metadata });
+    assert!(bin.attributes_differ());
+    assert!(bin.attr_diff.unwrap().entries);
+    let script = &bin.sub_diff.as_ref().unwrap().items[0];
+    assert_eq!(
+        script.attr_diff,
+        Some(AttrDiff {
+            mode: true,
+            ..Default::default()
+        })
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_dirdiff_links() {
+    use std::os::unix::fs::symlink;
+
+    use filetime::set_symlink_file_times;
+
+    let left_dir = tempfile::tempdir().unwrap();
+    let right_dir = tempfile::tempdir().unwrap();
+    let left_path = left_dir.path();
+    let right_path = right_dir.path();
+    for (path, mtime, target) in [
+        (left_path, 1000000, "one.txt"),
+        (right_path, 2000000, "two.txt"),
+    ] {
+        fs::write(path.join("one.txt"), "same content").unwrap();
+        fs::write(path.join("two.txt"), "same content").unwrap();
+        // Same target, created at different times
+        symlink("one.txt", path.join("same_target")).unwrap();
+        // Different targets with the same content
+        symlink(target, path.join("same_content")).unwrap();
+        for link in ["same_target", "same_content"] {
+            let time = FileTime::from_unix_time(mtime, 0);
+            set_symlink_file_times(path.join(link), time, time).unwrap();
+        }
+    }
+
+    let left_fsitem = Some(FSItem::new(left_path).await);
+    let right_fsitem = Some(FSItem::new(right_path).await);
+    let link_types = async |links: LinkMode| {
+        let options = DiffOptions {
+            links,
+            by_content: true,
+            ..Default::default()
+        };
+        DirDiff::with_options(&left_fsitem, &right_fsitem, options)
+            .await
+            .unwrap()
+            .items
+            .iter()
+            .filter(|item| item.left_item.as_ref().unwrap().is_link())
+            .map(|item| item.diff_item_type)
+            .collect::<Vec<_>>()
+    };
+    let right_newer = DiffItemType::Different {
+        newer: Some(DiffSide::Right),
+    };
+    // Items are sorted by name: same_content, same_target
+    assert_eq!(
+        link_types(LinkMode::Opaque).await,
+        [right_newer, right_newer]
+    );
+    assert_eq!(
+        link_types(LinkMode::Target).await,
+        [right_newer, DiffItemType::Same { by: By::LinkTarget }]
+    );
+    let same_by_content = DiffItemType::Same { by: By::Content };
+    assert_eq!(
+        link_types(LinkMode::Resolve).await,
+        [same_by_content, same_by_content]
+    );
+}
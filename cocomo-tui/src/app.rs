@@ -12,16 +12,18 @@
 //! This module contains the main application state and logic. It handles
 //! events, manages views (tabs), and drives the main loop.
 
-use std::io;
+use std::{env, io, path::PathBuf, process};
 
-use cocomo_core::FSItem;
+use cocomo_core::{
+    CopyOptions, DiffOptions, FSItem, Journal, TextDiffOptions,
+};
 use ratatui::{
     DefaultTerminal,
     buffer::Buffer,
     crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
     layout::{Constraint, Layout, Rect},
     style::{Color, Style},
-    widgets::{Block, Clear, Paragraph, Tabs, Widget},
+    widgets::{Block, Clear, Paragraph, Tabs, Widget, Wrap},
 };
 
 use crate::{
@@ -41,6 +43,24 @@ pub(crate) struct CmpItems {
     pub right: Option<FSItem>,
 }
 
+/// Maximum number of file system operations which can be undone.
+const UNDO_LIMIT: usize = 100;
+
+/// Returns the directory holding the backups of the journal of this
+/// session: `$XDG_CACHE_HOME/cocomo/backup-<pid>`, falling back to
+/// `~/.cache` and to the temporary directory. Backups of items on other file
+/// systems are kept in `.cocomo-<uid>/backup-<pid>` at the top of their
+/// mount point.
+fn backup_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::home_dir().map(|home| home.join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("cocomo")
+        .join(format!("backup-{}", process::id()))
+}
+
 /// Views available in the application.
 pub(crate) type AppView = Box<dyn NavigableView>;
 
@@ -57,17 +77,37 @@ pub(crate) struct App {
     active_view: usize,
     /// Flag to show a confirmation dialog before quitting.
     show_quit_confirm: bool,
+    /// The event (`CloseTab` or `Quit`) waiting for the user to decide
+    /// whether to save or discard unsaved modifications, if any.
+    save_prompt: Option<AppEvent>,
+    /// Options used for directory comparisons.
+    diff_options: DiffOptions,
+    /// Options used for text file comparisons.
+    text_options: TextDiffOptions,
+    /// Options used for copying and moving items.
+    copy_options: CopyOptions,
+    /// Journal of the file system operations performed in this session.
+    journal: Journal,
 }
 
 impl App {
     /// Constructs a new instance of [`App`].
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(
+        diff_options: DiffOptions,
+        text_options: TextDiffOptions,
+        copy_options: CopyOptions,
+    ) -> Self {
         Self {
             running: false,
             events: EventHandler::new(),
             views: vec![],
             active_view: 0,
             show_quit_confirm: false,
+            save_prompt: None,
+            diff_options,
+            text_options,
+            copy_options,
+            journal: Journal::new(backup_dir(), UNDO_LIMIT),
         }
     }
 
@@ -91,16 +131,42 @@ impl App {
         let view: AppView = match (left_item, right_item) {
             (Some(left), _) => {
                 if left.is_dir() {
-                    Box::new(DirView::new(left_item, right_item).await?)
+                    Box::new(DirView::new(
+                        left_item,
+                        right_item,
+                        self.diff_options.clone(),
+                        self.copy_options.clone(),
+                        self.journal.clone(),
+                    ))
                 } else {
-                    Box::new(TextView::new(left_item, right_item).await?)
+                    Box::new(
+                        TextView::new(
+                            left_item,
+                            right_item,
+                            self.text_options.clone(),
+                        )
+                        .await?,
+                    )
                 }
             }
             (_, Some(right)) => {
                 if right.is_dir() {
-                    Box::new(DirView::new(left_item, right_item).await?)
+                    Box::new(DirView::new(
+                        left_item,
+                        right_item,
+                        self.diff_options.clone(),
+                        self.copy_options.clone(),
+                        self.journal.clone(),
+                    ))
                 } else {
-                    Box::new(TextView::new(left_item, right_item).await?)
+                    Box::new(
+                        TextView::new(
+                            left_item,
+                            right_item,
+                            self.text_options.clone(),
+                        )
+                        .await?,
+                    )
                 }
             }
             _ => unreachable!(),
@@ -139,6 +205,8 @@ impl App {
                 }
             }
         }
+        // The operations can't be undone after quitting
+        self.journal.clear().await?;
         Ok(())
     }
 
@@ -147,6 +215,7 @@ impl App {
     /// # Errors
     ///
     /// Returns an error if an application event cannot be sent.
+    #[allow(clippy::collapsible_match)]
     fn handle_key_event(
         &mut self,
         key_event: KeyEvent,
@@ -163,6 +232,46 @@ impl App {
             }
             return Ok(());
         }
+        if let Some(app_event) = self.save_prompt.take() {
+            let closing_tab = matches!(app_event, AppEvent::CloseTab);
+            match key_event.code {
+                KeyCode::Char('s') => {
+                    for (i, view) in self.views.iter_mut().enumerate() {
+                        if view.is_modified()
+                            && (!closing_tab || i == self.active_view)
+                        {
+                            view.handle_app_event(AppEvent::Save)?;
+                        }
+                    }
+                    // Saving may fail: keep asking, showing the first tab
+                    // still modified with its error
+                    if closing_tab {
+                        if self.current_view().is_modified() {
+                            self.save_prompt = Some(app_event);
+                        } else {
+                            self.remove_tab();
+                        }
+                    } else if let Some(idx) =
+                        self.views.iter().position(|view| view.is_modified())
+                    {
+                        self.active_view = idx;
+                        self.save_prompt = Some(app_event);
+                    } else {
+                        self.quit();
+                    }
+                }
+                KeyCode::Char('d') if closing_tab => self.remove_tab(),
+                KeyCode::Char('d') => self.quit(),
+                KeyCode::Esc => {}
+                _ => self.save_prompt = Some(app_event),
+            }
+            return Ok(());
+        }
+        if !self.views.is_empty()
+            && self.current_view_mut().handle_key_event(key_event)
+        {
+            return Ok(());
+        }
         match (key_event.code, key_event.modifiers) {
             (KeyCode::Char('q'), KeyModifiers::NONE) => {
                 self.events.send(AppEvent::Quit);
@@ -206,9 +315,27 @@ impl App {
             (KeyCode::Char('m'), KeyModifiers::NONE) => {
                 self.events.send(AppEvent::Move);
             }
+            (KeyCode::Char('a'), KeyModifiers::NONE) => {
+                self.events.send(AppEvent::ApplyRename);
+            }
             (KeyCode::Char('d'), KeyModifiers::NONE) => {
+                self.events.send(AppEvent::Trash);
+            }
+            (KeyCode::Char('D'), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
                 self.events.send(AppEvent::Delete);
             }
+            (KeyCode::Char('u'), KeyModifiers::NONE) => {
+                self.events.send(AppEvent::Undo);
+            }
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.events.send(AppEvent::Redo);
+            }
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                self.events.send(AppEvent::Save);
+            }
+            (KeyCode::Esc, KeyModifiers::NONE) => {
+                self.events.send(AppEvent::Abort);
+            }
             _ => {}
         }
         Ok(())
@@ -219,16 +346,30 @@ impl App {
     /// The tick event is where you can update the state of your application
     /// with any logic that needs to be updated at a fixed frame rate. E.g.
     /// polling a server, updating an animation.
-    #[allow(clippy::unused_self)]
-    pub const fn tick(&self) {}
+    pub fn tick(&mut self) {
+        for view in &mut self.views {
+            view.tick();
+        }
+    }
 
     /// Set running to false to quit the application.
     pub const fn quit(&mut self) {
         self.running = false;
     }
 
-    /// Closes the current tab.
+    /// Closes the current tab, asking the user whether to save or discard
+    /// unsaved modifications first.
     pub fn close_tab(&mut self) {
+        if self.current_view().is_modified() {
+            self.save_prompt = Some(AppEvent::CloseTab);
+            return;
+        }
+        self.remove_tab();
+    }
+
+    /// Removes the current tab, asking for confirmation before removing the
+    /// last one.
+    fn remove_tab(&mut self) {
         if self.views.len() == 1 {
             self.show_quit_confirm = true;
             return;
@@ -245,7 +386,16 @@ impl App {
         app_event: AppEvent,
     ) -> color_eyre::Result<()> {
         match app_event {
-            AppEvent::Quit => self.quit(),
+            AppEvent::Quit => {
+                if let Some(i) =
+                    self.views.iter().position(|view| view.is_modified())
+                {
+                    self.active_view = i;
+                    self.save_prompt = Some(AppEvent::Quit);
+                } else {
+                    self.quit();
+                }
+            }
             AppEvent::NavigatePrev => {
                 let view = self.current_view_mut();
                 view.prev();
@@ -265,9 +415,20 @@ impl App {
             AppEvent::CloseTab => self.close_tab(),
             AppEvent::OpenView => {
                 if let Some(item) = self.current_view().current_diff_item() {
-                    let left_item = item.left_item.clone();
-                    let right_item = item.right_item.clone();
-                    self.new_view(&left_item, &right_item).await?;
+                    if let Some(sub_diff) = &item.sub_diff {
+                        // subtree has already been compared
+                        let view = DirView::from_diff(
+                            *sub_diff.clone(),
+                            self.copy_options.clone(),
+                            self.journal.clone(),
+                        );
+                        self.views.push(Box::new(view));
+                        self.active_view = self.views.len() - 1;
+                    } else {
+                        let left_item = item.left_item.clone();
+                        let right_item = item.right_item.clone();
+                        self.new_view(&left_item, &right_item).await?;
+                    }
                 };
             }
             _ => {
@@ -294,7 +455,9 @@ impl Widget for &App {
         // Render key hints
         Paragraph::new(
             "q: quit | x: close tab | Enter: open | Tab: switch | ↑/↓: \
-             navigate | Home/End: top/bottom | c: copy | m: move | d: delete",
+             navigate | Home/End: top/bottom | c: copy | m: move | a: apply \
+             rename | d: trash | D: delete | u: undo | ^R: redo | ^S: save | \
+             Esc: abort",
         )
         .left_aligned()
         .render(key_bar, buf);
@@ -311,6 +474,23 @@ impl Widget for &App {
         // Render current view
         self.current_view().render_ref(main_view, buf);
 
+        if let Some(app_event) = &self.save_prompt {
+            let area = centered_rect(50, 20, area);
+            Clear.render(area, buf);
+            let text = if matches!(app_event, AppEvent::CloseTab) {
+                "Save the modifications before closing the tab?\n\ns: save | \
+                 d: discard | Esc: cancel"
+            } else {
+                "Save the modifications of all tabs before quitting?\n\ns: \
+                 save | d: discard | Esc: cancel"
+            };
+            Paragraph::new(text)
+                .centered()
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().title("Unsaved modifications"))
+                .render(area, buf);
+        }
+
         if self.show_quit_confirm {
             let area = centered_rect(40, 10, area);
             Clear.render(area, buf);
@@ -326,7 +506,7 @@ impl Widget for &App {
 /// helper function to create a centered rect using up certain % of the
 /// available rect `r`
 #[allow(clippy::integer_division)]
-fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+pub(crate) fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::vertical([
         Constraint::Percentage((100 - percent_y) / 2),
         Constraint::Percentage(percent_y),
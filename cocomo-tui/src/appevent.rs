@@ -29,10 +29,22 @@ pub(crate) enum AppEvent {
     Copy,
     /// Move the current item to the other side.
     Move,
-    /// Delete the current item.
+    /// Apply the renaming of the current item to the right side.
+    ApplyRename,
+    /// Move the current item to the trash.
+    Trash,
+    /// Delete the current item permanently.
     Delete,
     /// Rename the current item.
     Rename,
+    /// Undo the last file system operation.
+    Undo,
+    /// Redo the last file system operation undone.
+    Redo,
+    /// Save the modifications made in the current view.
+    Save,
     /// Refresh the current view.
     Refresh,
+    /// Abort the operation running in the current view.
+    Abort,
 }
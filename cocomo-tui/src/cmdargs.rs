@@ -12,9 +12,41 @@
 //! This module defines the command line arguments for the Cocomo TUI
 //! application and uses `clap` for parsing.
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, ValueEnum, error::ErrorKind};
+use cocomo_core::{
+    CancelToken, CopyOptions, DEFAULT_CONTEXT, DiffOptions, Encoding,
+    ItemFilter, LinkMode, TextDiffOptions, WhitespaceMode,
+};
+
+/// Modes for comparing symbolic links.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+enum Links {
+    /// Compare the links themselves by their metadata
+    #[default]
+    Opaque,
+    /// Compare the target paths of the links
+    Target,
+    /// Compare the items the links resolve to
+    Resolve,
+}
+
+impl From<Links> for LinkMode {
+    fn from(links: Links) -> Self {
+        match links {
+            Links::Opaque => Self::Opaque,
+            Links::Target => Self::Target,
+            Links::Resolve => Self::Resolve,
+        }
+    }
+}
+
+/// Parses the label of a character encoding, e.g. `latin1` or `utf-16le`.
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("unknown encoding '{label}'"))
+}
 
 #[derive(Clone, Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -26,6 +58,114 @@ struct Args {
     /// Right-side directory / file
     #[clap(short, long)]
     right: Option<PathBuf>,
+
+    /// Compare the content of files, not only their metadata
+    #[clap(short = 'C', long)]
+    content: bool,
+
+    /// Compare subdirectories recursively
+    #[clap(short = 'R', long)]
+    recursive: bool,
+
+    /// How to compare symbolic links existing on both sides
+    #[clap(short = 'L', long, value_enum, default_value_t)]
+    links: Links,
+
+    /// Exclude entries matching the given pattern (gitignore syntax, may be
+    /// given multiple times)
+    #[clap(short = 'x', long = "exclude", value_name = "PATTERN")]
+    excludes: Vec<String>,
+
+    /// Honour .gitignore and .ignore files
+    #[clap(short = 'i', long)]
+    ignore_files: bool,
+
+    /// Regard modification times differing by at most the given number of
+    /// seconds as equal
+    #[clap(short = 't', long, value_name = "SECONDS", default_value_t = 0)]
+    mtime_tolerance: u64,
+
+    /// Regard modification times differing by a whole number of hours up to
+    /// the given one as equal (compensates DST shifts / time zone offsets)
+    #[clap(
+        long,
+        value_name = "HOURS",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u32).range(0..=24)
+    )]
+    hour_offset: u32,
+
+    /// Ignore modification times, compare size and content instead
+    #[clap(short = 'T', long)]
+    ignore_mtime: bool,
+
+    /// Compare permissions, owner, group, extended attributes and ACLs
+    #[clap(short = 'A', long)]
+    attributes: bool,
+
+    /// Detect files renamed or moved between both sides
+    #[clap(short = 'M', long)]
+    renames: bool,
+
+    /// Pair text files with different content as renamed if at least the
+    /// given share (0 to 1) of their lines match (implies --renames)
+    #[clap(long, value_name = "RATIO", default_value_t = 0.0)]
+    rename_similarity: f32,
+
+    /// Ignore whitespace at the end of lines when comparing text files
+    #[clap(short = 'Z', long)]
+    ignore_trailing_space: bool,
+
+    /// Ignore changes in the amount of whitespace when comparing text files
+    #[clap(short = 'b', long)]
+    ignore_space_change: bool,
+
+    /// Ignore all whitespace when comparing text files
+    #[clap(short = 'w', long)]
+    ignore_all_space: bool,
+
+    /// Ignore added or removed blank lines when comparing text files
+    #[clap(short = 'B', long)]
+    ignore_blank_lines: bool,
+
+    /// Ignore case when comparing text files
+    #[clap(long)]
+    ignore_case: bool,
+
+    /// Ignore differences between CRLF and LF line endings when comparing
+    /// text files
+    #[clap(short = 'E', long)]
+    normalize_line_endings: bool,
+
+    /// Character encoding of the text files on both sides (detected by
+    /// default)
+    #[clap(long, value_name = "ENCODING", value_parser = parse_encoding)]
+    encoding: Option<&'static Encoding>,
+
+    /// Character encoding of the text files on the left side
+    #[clap(long, value_name = "ENCODING", value_parser = parse_encoding)]
+    left_encoding: Option<&'static Encoding>,
+
+    /// Character encoding of the text files on the right side
+    #[clap(long, value_name = "ENCODING", value_parser = parse_encoding)]
+    right_encoding: Option<&'static Encoding>,
+
+    /// Maximum number of files read concurrently (0: number of CPUs)
+    #[clap(short = 'j', long, value_name = "N", default_value_t = 0)]
+    jobs: usize,
+
+    /// Verify copied files by comparing checksums
+    #[clap(long)]
+    verify: bool,
+
+    /// Print the differences as a unified diff (patch) instead of starting
+    /// the user interface
+    #[clap(short = 'p', long)]
+    patch: bool,
+
+    /// Number of unchanged lines shown around the differences in a patch
+    #[clap(short = 'U', long, value_name = "N", default_value_t = DEFAULT_CONTEXT)]
+    unified: usize,
 }
 
 /// Command line arguments for the application.
@@ -34,15 +174,72 @@ pub(crate) struct CmdLineArgs {
     pub(crate) left: Option<PathBuf>,
     /// Path to the right side directory or file.
     pub(crate) right: Option<PathBuf>,
+    /// Options for comparing directories.
+    pub(crate) diff_options: DiffOptions,
+    /// Options for comparing text files.
+    pub(crate) text_options: TextDiffOptions,
+    /// Options for copying and moving items.
+    pub(crate) copy_options: CopyOptions,
+    /// Number of context lines of the patch to print instead of starting the
+    /// user interface, if any.
+    pub(crate) patch_context: Option<usize>,
 }
 
 impl CmdLineArgs {
     /// Parses the command line arguments and returns a `CmdLineArgs` instance.
     pub(crate) fn get() -> Self {
         let args = Args::parse();
+        // Ignore files above the compared directories don't apply, not
+        // even when comparing a subdirectory in another tab
+        let filter = ItemFilter::new(&args.excludes, args.ignore_files)
+            .unwrap_or_else(|err| {
+                Args::command().error(ErrorKind::InvalidValue, err).exit()
+            })
+            .with_roots(
+                [args.left.clone().unwrap_or_else(|| ".".into())]
+                    .into_iter()
+                    .chain(args.right.clone()),
+            );
         Self {
             left: args.left,
             right: args.right,
+            diff_options: DiffOptions {
+                by_content: args.content,
+                recursive: args.recursive,
+                links: args.links.into(),
+                filter,
+                mtime_tolerance: Duration::from_secs(args.mtime_tolerance),
+                max_hour_offset: args.hour_offset,
+                ignore_mtime: args.ignore_mtime,
+                detect_renames: args.renames || args.rename_similarity > 0.0,
+                rename_similarity: args.rename_similarity,
+                compare_attributes: args.attributes,
+                parallelism: args.jobs,
+                progress: None,
+                cancel: CancelToken::new(),
+            },
+            text_options: TextDiffOptions {
+                whitespace: if args.ignore_all_space {
+                    WhitespaceMode::IgnoreAll
+                } else if args.ignore_space_change {
+                    WhitespaceMode::IgnoreAmount
+                } else if args.ignore_trailing_space {
+                    WhitespaceMode::IgnoreTrailing
+                } else {
+                    WhitespaceMode::Exact
+                },
+                ignore_blank_lines: args.ignore_blank_lines,
+                ignore_case: args.ignore_case,
+                normalize_line_endings: args.normalize_line_endings,
+                left_encoding: args.left_encoding.or(args.encoding),
+                right_encoding: args.right_encoding.or(args.encoding),
+                cancel: CancelToken::new(),
+            },
+            copy_options: CopyOptions {
+                verify: args.verify,
+                ..Default::default()
+            },
+            patch_context: args.patch.then_some(args.unified),
         }
     }
 }
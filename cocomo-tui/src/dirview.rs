@@ -15,29 +15,26 @@
 use std::{cell, io, path};
 
 use cocomo_core::{
-    By,
-    DiffItem,
-    DiffItemType,
-    DiffSide,
-    DirDiff,
-    FSItem,
-    copy_item,
-    delete_item,
-    move_item, // rename_item,
+    Action, AttrDiff, By, CancelToken, ConflictPolicy, CopyOptions, DiffItem,
+    DiffItemType, DiffOptions, DiffSide, DirDiff, FSItem, FsError, Journal,
+    Operation, Plan, ScanEvent, plan, relative_path,
 };
 use futures::executor::block_on;
 use ratatui::{
     buffer::Buffer,
+    crossterm::event::{KeyCode, KeyEvent},
     layout::{Constraint, Layout, Rect},
     style::{Color, Style},
     text::Text,
     widgets::{
-        Cell, Paragraph, Row, StatefulWidget, Table, TableState, Widget,
-        WidgetRef,
+        Block, Cell, Clear, Paragraph, Row, StatefulWidget, Table, TableState,
+        Widget, WidgetRef, Wrap,
     },
 };
+use tokio::{sync::mpsc, task::JoinHandle};
 
 use crate::{
+    app::centered_rect,
     appevent::AppEvent,
     view::{NavigableView, View},
 };
@@ -55,13 +52,234 @@ fn indicator<'a>(t: DiffItemType) -> Text<'a> {
         DiffItemType::Same { by } => match by {
             By::Metadata => ("≟", Color::White),
             By::Content => ("=", Color::White),
+            By::Inode => ("≡", Color::White),
+            By::LinkTarget => ("=", Color::Cyan),
         },
+        DiffItemType::Renamed { identical: true } => ("↷", Color::Cyan),
+        DiffItemType::Renamed { identical: false } => ("↷", Color::Yellow),
     };
     Text::from(char)
         .style(Style::default().fg(color).bold())
         .centered()
 }
 
+/// Map the attributes differing to indicator text: `m`ode, `o`wner,
+/// `g`roup, `x`attrs, `a`cl and `+` for entries with differing attributes.
+fn attr_indicator<'a>(attr_diff: Option<AttrDiff>) -> Text<'a> {
+    let Some(attr_diff) = attr_diff else {
+        return Text::default();
+    };
+    let text: String = [
+        (attr_diff.mode, 'm'),
+        (attr_diff.owner, 'o'),
+        (attr_diff.group, 'g'),
+        (attr_diff.xattrs, 'x'),
+        (attr_diff.acl, 'a'),
+        (attr_diff.entries, '+'),
+    ]
+    .into_iter()
+    .filter_map(|(differs, c)| differs.then_some(c))
+    .collect();
+    Text::from(text).style(Style::default().fg(Color::Magenta).bold())
+}
+
+/// A comparison running in the background.
+#[derive(Debug)]
+struct PendingScan {
+    /// The task running the comparison.
+    task: JoinHandle<io::Result<DirDiff>>,
+    /// Receiver of the progress events.
+    events: mpsc::UnboundedReceiver<ScanEvent>,
+    /// Number of entries read so far.
+    items_scanned: u64,
+    /// Number of bytes read so far for comparing file content.
+    bytes_read: u64,
+    /// The directory compared last.
+    last_dir: Option<path::PathBuf>,
+}
+
+/// Formats a number of bytes for display.
+#[allow(clippy::cast_precision_loss)]
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Returns the side and the item to be deleted or trashed for `item`: the
+/// older or only one, or the left one if there is no such.
+const fn deletion_target(item: &DiffItem) -> (DiffSide, &FSItem) {
+    match item.diff_item_type {
+        DiffItemType::LeftOnly
+        | DiffItemType::Different { newer: None }
+        | DiffItemType::Different {
+            newer: Some(DiffSide::Right),
+        }
+        | DiffItemType::Same { .. }
+        | DiffItemType::Renamed { .. } => {
+            (DiffSide::Left, item.left_item.as_ref().unwrap())
+        }
+        DiffItemType::RightOnly
+        | DiffItemType::Different {
+            newer: Some(DiffSide::Left),
+        } => (DiffSide::Right, item.right_item.as_ref().unwrap()),
+    }
+}
+
+/// Returns the name of `item` for display in the directory `dir`, which is
+/// its path relative to `dir` if it resides in another directory, followed
+/// by the target of a symbolic link.
+fn display_name(item: &FSItem, dir: Option<&FSItem>) -> String {
+    let mut name = match dir {
+        Some(dir) if item.path().parent() != Some(dir.path().as_path()) => {
+            relative_path(dir.path(), item.path())
+                .to_string_lossy()
+                .into_owned()
+        }
+        _ => item.name().to_string_lossy().into_owned(),
+    };
+    if item.is_dir() {
+        name += path::MAIN_SEPARATOR_STR;
+    }
+    if let Some(target) = item.link_target() {
+        name += " → ";
+        name += &target.to_string_lossy();
+    }
+    name
+}
+
+/// A file system operation on a compared item.
+#[derive(Clone, Debug)]
+enum FileOp {
+    /// Copy `src` to the directory `dst`.
+    Copy { src: FSItem, dst: path::PathBuf },
+    /// Move `src` to the directory `dst`.
+    Move { src: FSItem, dst: path::PathBuf },
+    /// Move `src` to the path `dst`, applying the renaming of a file on the
+    /// other side.
+    Rename { src: FSItem, dst: path::PathBuf },
+    /// Move `target` to the trash.
+    Trash { target: FSItem },
+    /// Delete `target` permanently.
+    Delete { target: FSItem },
+    /// Undo the last operation.
+    Undo,
+    /// Redo the last operation undone.
+    Redo,
+}
+
+impl FileOp {
+    /// Returns a description of the operation.
+    fn description(&self) -> String {
+        match self {
+            Self::Copy { src, .. } => {
+                format!("Copying {}", src.name().to_string_lossy())
+            }
+            Self::Move { src, .. } => {
+                format!("Moving {}", src.name().to_string_lossy())
+            }
+            Self::Rename { src, dst } => format!(
+                "Renaming {} to {}",
+                src.name().to_string_lossy(),
+                dst.display()
+            ),
+            Self::Trash { target } => format!(
+                "Moving {} to the trash",
+                target.name().to_string_lossy()
+            ),
+            Self::Delete { target } => {
+                format!("Deleting {}", target.name().to_string_lossy())
+            }
+            Self::Undo => "Undoing the last operation".to_owned(),
+            Self::Redo => "Redoing the last operation".to_owned(),
+        }
+    }
+
+    /// Runs the operation, recording it in `journal`.
+    async fn run(
+        self,
+        options: CopyOptions,
+        cancel: CancelToken,
+        journal: Journal,
+    ) -> Result<(), FsError> {
+        let (item, operation) = match self {
+            Self::Copy { src, dst } => (src, Operation::Copy { to: dst }),
+            Self::Move { src, dst } | Self::Rename { src, dst } => {
+                (src, Operation::Move { to: dst })
+            }
+            Self::Trash { target } => (target, Operation::Trash),
+            Self::Delete { target } => (target, Operation::Delete),
+            Self::Undo => return journal.undo(&cancel).await.map(drop),
+            Self::Redo => return journal.redo(&cancel).await.map(drop),
+        };
+        journal.apply(&item, &operation, &options, &cancel).await
+    }
+}
+
+/// A file system operation running in the background.
+#[derive(Debug)]
+struct PendingOp {
+    /// The operation.
+    op: FileOp,
+    /// The task running the operation.
+    task: JoinHandle<Result<(), FsError>>,
+}
+
+/// An operation waiting for the user to confirm its planned actions.
+#[derive(Debug)]
+struct Confirmation {
+    /// The operation.
+    op: FileOp,
+    /// The actions the operation is going to perform.
+    plan: Plan,
+}
+
+impl Confirmation {
+    /// Returns the text asking the user for confirmation.
+    fn text(&self) -> String {
+        let (mut files, mut dirs, mut bytes) = (0, 0, 0);
+        for action in &self.plan.actions {
+            match action {
+                Action::DeleteFile { size, .. } => {
+                    files += 1;
+                    bytes += size;
+                }
+                Action::DeleteDir { .. } => dirs += 1,
+                _ => {}
+            }
+        }
+        format!(
+            "{}: {} files ({}) and {} directories will be deleted \
+             permanently.\n\ny: confirm | n: cancel",
+            self.op.description(),
+            files,
+            format_size(bytes),
+            dirs
+        )
+    }
+}
+
+/// An operation waiting for the user to decide how to handle an item
+/// already existing at its destination.
+#[derive(Debug)]
+struct Conflict {
+    /// The operation.
+    op: FileOp,
+    /// The path of the existing item.
+    path: path::PathBuf,
+    /// The existing item is a directory in the way of a file or link, which
+    /// is overwritten only if the user confirms it.
+    dir_in_the_way: bool,
+}
+
 /// View for displaying directory comparison results.
 #[derive(Debug)]
 pub struct DirView {
@@ -69,23 +287,227 @@ pub struct DirView {
     diff: DirDiff,
     /// The state of the table.
     table_state: cell::RefCell<TableState>,
+    /// The comparison currently running in the background, if any.
+    scan: Option<PendingScan>,
+    /// The file system operation currently running in the background, if
+    /// any.
+    op: Option<PendingOp>,
+    /// The operation waiting for the user to confirm it, if any.
+    confirmation: Option<Confirmation>,
+    /// The operation waiting for the user to resolve a conflict, if any.
+    conflict: Option<Conflict>,
+    /// Token for cancelling the work currently running in the background.
+    cancel: CancelToken,
+    /// The error reported by the last comparison or operation, if any.
+    error: Option<String>,
+    /// Options for copying and moving items.
+    copy_options: CopyOptions,
+    /// Journal recording the file system operations.
+    journal: Journal,
 }
 
 impl DirView {
-    /// Creates a new `DirView` from the given file system items.
-    pub async fn new(
+    /// Creates a new `DirView` from the given file system items, comparing
+    /// them according to the given options in the background, copying and
+    /// moving items according to `copy_options` and recording file system
+    /// operations in `journal`.
+    pub fn new(
         left_item: &Option<FSItem>,
         right_item: &Option<FSItem>,
-    ) -> io::Result<Self> {
-        let diff = DirDiff::new(left_item, right_item).await?;
+        options: DiffOptions,
+        copy_options: CopyOptions,
+        journal: Journal,
+    ) -> Self {
+        let mut view = Self::from_diff(
+            DirDiff {
+                left_dir: left_item.clone(),
+                right_dir: right_item.clone(),
+                items: Vec::new(),
+                options,
+            },
+            copy_options,
+            journal,
+        );
+        view.start_scan();
+        view
+    }
+
+    /// Creates a new `DirView` from an existing comparison result, copying
+    /// and moving items according to `copy_options` and recording file
+    /// system operations in `journal`.
+    pub fn from_diff(
+        diff: DirDiff,
+        copy_options: CopyOptions,
+        journal: Journal,
+    ) -> Self {
         let mut table_state = TableState::default();
         if !diff.items.is_empty() {
             table_state.select(Some(0));
         }
-        Ok(Self {
+        Self {
             diff,
             table_state: cell::RefCell::new(table_state),
-        })
+            scan: None,
+            op: None,
+            confirmation: None,
+            conflict: None,
+            cancel: CancelToken::new(),
+            error: None,
+            copy_options,
+            journal,
+        }
+    }
+
+    /// Starts (re-)comparing the directories in the background.
+    ///
+    /// The current results are kept until the comparison is finished.
+    fn start_scan(&mut self) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let left_dir = self.diff.left_dir.clone();
+        let right_dir = self.diff.right_dir.clone();
+        self.cancel = CancelToken::new();
+        let options = DiffOptions {
+            progress: Some(sender),
+            cancel: self.cancel.clone(),
+            ..self.diff.options.clone()
+        };
+        let task = tokio::spawn(async move {
+            DirDiff::with_options(&left_dir, &right_dir, options).await
+        });
+        self.scan = Some(PendingScan {
+            task,
+            events: receiver,
+            items_scanned: 0,
+            bytes_read: 0,
+            last_dir: None,
+        });
+    }
+
+    /// Starts the file system operation `op` in the background, resolving
+    /// conflicts according to `on_conflict`.
+    ///
+    /// Once finished, the directories are compared again.
+    fn start_op(&mut self, op: FileOp, on_conflict: ConflictPolicy) {
+        let options = CopyOptions {
+            on_conflict,
+            ..self.copy_options.clone()
+        };
+        self.start_op_with(op, options);
+    }
+
+    /// Starts the file system operation `op` in the background with the
+    /// given `options`.
+    ///
+    /// Once finished, the directories are compared again.
+    fn start_op_with(&mut self, op: FileOp, options: CopyOptions) {
+        self.cancel = CancelToken::new();
+        self.error = None;
+        let task = tokio::spawn(op.clone().run(
+            options,
+            self.cancel.clone(),
+            self.journal.clone(),
+        ));
+        self.op = Some(PendingOp { op, task });
+    }
+
+    /// Checks whether the file system operation running in the background
+    /// is finished and, if so, starts re-comparing the directories.
+    ///
+    /// If the operation found an item already existing at its destination,
+    /// or a directory in the way to be overwritten, the user is asked how to
+    /// proceed instead. Items removed without backup are reported.
+    fn poll_op(&mut self) {
+        if !self.op.as_ref().is_some_and(|op| op.task.is_finished()) {
+            return;
+        }
+        let Some(op) = self.op.take() else {
+            return;
+        };
+        // The task is finished, so this does not block.
+        match block_on(op.task) {
+            Ok(Ok(())) => {}
+            Ok(Err(FsError::DestinationAlreadyExists(path)))
+                if !matches!(op.op, FileOp::Undo | FileOp::Redo) =>
+            {
+                // Conflicts are checked before anything gets changed, so
+                // the operation can be retried as a whole
+                self.conflict = Some(Conflict {
+                    op: op.op,
+                    path,
+                    dir_in_the_way: false,
+                });
+                return;
+            }
+            Ok(Err(FsError::DirectoryInTheWay(path)))
+                if !matches!(op.op, FileOp::Undo | FileOp::Redo) =>
+            {
+                self.conflict = Some(Conflict {
+                    op: op.op,
+                    path,
+                    dir_in_the_way: true,
+                });
+                return;
+            }
+            Ok(Err(err)) => self.error = Some(err.to_string()),
+            Err(err) => self.error = Some(err.to_string()),
+        }
+        let unrecoverable = self.journal.unrecoverable();
+        if let Some(first) = unrecoverable.first()
+            && !matches!(op.op, FileOp::Undo)
+        {
+            let more = match unrecoverable.len() {
+                1 => String::new(),
+                n => format!(" and {} more", n - 1),
+            };
+            let notice = format!(
+                "no backup kept, can't be undone: {}{}",
+                first.display(),
+                more
+            );
+            self.error = Some(match self.error.take() {
+                Some(err) => format!("{err}; {notice}"),
+                None => notice,
+            });
+        }
+        self.start_scan();
+    }
+
+    /// Takes over the progress and, once finished, the result of the
+    /// comparison running in the background.
+    fn poll_scan(&mut self) {
+        let Some(scan) = self.scan.as_mut() else {
+            return;
+        };
+        while let Ok(event) = scan.events.try_recv() {
+            match event {
+                ScanEvent::Scanned(_) => scan.items_scanned += 1,
+                ScanEvent::Read(n) => scan.bytes_read += n,
+                ScanEvent::Compared(dir) => scan.last_dir = Some(dir),
+            }
+        }
+        if !scan.task.is_finished() {
+            return;
+        }
+        let Some(scan) = self.scan.take() else {
+            return;
+        };
+        // The task is finished, so this does not block.
+        match block_on(scan.task) {
+            Ok(Ok(mut diff)) => {
+                diff.options.progress = None;
+                diff.options.cancel = CancelToken::new();
+                self.diff = diff;
+                let mut table_state = self.table_state.borrow_mut();
+                let selected = match table_state.selected() {
+                    _ if self.diff.items.is_empty() => None,
+                    Some(i) => Some(i.min(self.diff.items.len() - 1)),
+                    None => Some(0),
+                };
+                table_state.select(selected);
+            }
+            Ok(Err(err)) => self.error = Some(err.to_string()),
+            Err(err) => self.error = Some(err.to_string()),
+        }
     }
 
     pub(crate) async fn handle_app_event(
@@ -94,6 +516,18 @@ impl DirView {
     ) -> color_eyre::Result<()> {
         let left_dir = &self.diff.left_dir;
         let right_dir = &self.diff.right_dir;
+        if matches!(app_event, AppEvent::Abort) {
+            self.cancel.cancel();
+            return Ok(());
+        }
+        if self.scan.is_some()
+            || self.op.is_some()
+            || self.confirmation.is_some()
+            || self.conflict.is_some()
+        {
+            // Don't operate on outdated results
+            return Ok(());
+        }
         match app_event {
             AppEvent::Copy => {
                 if let Some(item) = self.current_diff_item()
@@ -105,7 +539,8 @@ impl DirView {
                         | DiffItemType::Different {
                             newer: Some(DiffSide::Left),
                         }
-                        | DiffItemType::Different { newer: None } => (
+                        | DiffItemType::Different { newer: None }
+                        | DiffItemType::Renamed { .. } => (
                             item.left_item.as_ref().unwrap(),
                             right_dir.as_ref().unwrap(),
                         ),
@@ -117,7 +552,11 @@ impl DirView {
                             left_dir.as_ref().unwrap(),
                         ),
                         DiffItemType::Same { by } => {
-                            if by == By::Content {
+                            // Hard links share their content with the other
+                            // side, which is gone with the last one
+                            if matches!(by, By::Content | By::LinkTarget)
+                                && !item.attributes_differ()
+                            {
                                 return Ok(());
                             };
                             (
@@ -126,8 +565,11 @@ impl DirView {
                             )
                         }
                     };
-                    copy_item(src, dst.path()).await?;
-                    self.diff.refresh().await?;
+                    let op = FileOp::Copy {
+                        src: src.clone(),
+                        dst: dst.path().clone(),
+                    };
+                    self.start_op(op, ConflictPolicy::Ask);
                 }
             }
             AppEvent::Move => {
@@ -140,7 +582,8 @@ impl DirView {
                         | DiffItemType::Different {
                             newer: Some(DiffSide::Left),
                         }
-                        | DiffItemType::Different { newer: None } => (
+                        | DiffItemType::Different { newer: None }
+                        | DiffItemType::Renamed { .. } => (
                             item.left_item.as_ref().unwrap(),
                             right_dir.as_ref().unwrap(),
                         ),
@@ -152,9 +595,12 @@ impl DirView {
                             left_dir.as_ref().unwrap(),
                         ),
                         DiffItemType::Same { by } => {
-                            if by == By::Content {
-                                delete_item(item.left_item.as_ref().unwrap())
-                                    .await?;
+                            if by != By::Metadata && !item.attributes_differ()
+                            {
+                                let op = FileOp::Trash {
+                                    target: item.left_item.clone().unwrap(),
+                                };
+                                self.start_op(op, ConflictPolicy::Ask);
                                 return Ok(());
                             }
                             (
@@ -163,35 +609,76 @@ impl DirView {
                             )
                         }
                     };
-                    move_item(src, dst.path()).await?;
-                    self.diff.refresh().await?;
+                    let op = FileOp::Move {
+                        src: src.clone(),
+                        dst: dst.path().clone(),
+                    };
+                    self.start_op(op, ConflictPolicy::Ask);
+                }
+            }
+            AppEvent::ApplyRename => {
+                if let Some(item) = self.current_diff_item()
+                    && let DiffItemType::Renamed { .. } = item.diff_item_type
+                    && let (Some(left_dir), Some(right_dir)) =
+                        (left_dir, right_dir)
+                    && let (Some(left), Some(right)) =
+                        (&item.left_item, &item.right_item)
+                {
+                    // Give the right file the path of the left one
+                    let dst = right_dir
+                        .path()
+                        .join(relative_path(left_dir.path(), left.path()));
+                    let op = FileOp::Rename {
+                        src: right.clone(),
+                        dst,
+                    };
+                    self.start_op(op, ConflictPolicy::Ask);
+                }
+            }
+            AppEvent::Trash => {
+                if let Some(item) = self.current_diff_item() {
+                    let (_, target) = deletion_target(item);
+                    let op = FileOp::Trash {
+                        target: target.clone(),
+                    };
+                    self.start_op(op, ConflictPolicy::Ask);
                 }
             }
             AppEvent::Delete => {
                 if let Some(item) = self.current_diff_item() {
-                    let target = match item.diff_item_type {
-                        DiffItemType::LeftOnly
-                        | DiffItemType::Different { newer: None }
-                        | DiffItemType::Different {
-                            newer: Some(DiffSide::Right),
-                        }
-                        | DiffItemType::Same { .. } => {
-                            item.left_item.as_ref().unwrap()
-                        }
-                        DiffItemType::RightOnly
-                        | DiffItemType::Different {
-                            newer: Some(DiffSide::Left),
-                        } => item.right_item.as_ref().unwrap(),
+                    let (side, target) = deletion_target(item);
+                    let op = FileOp::Delete {
+                        target: target.clone(),
                     };
-                    delete_item(target).await?;
-                    self.diff.refresh().await?;
+                    // Let the user review what is going to be deleted
+                    match plan(
+                        item,
+                        side,
+                        &Operation::Delete,
+                        &self.copy_options,
+                    )
+                    .await
+                    {
+                        Ok(plan) => {
+                            self.confirmation =
+                                Some(Confirmation { op, plan });
+                        }
+                        Err(err) => self.error = Some(err.to_string()),
+                    }
                 }
             }
+            AppEvent::Undo if self.journal.can_undo() => {
+                self.start_op(FileOp::Undo, ConflictPolicy::Ask);
+            }
+            AppEvent::Redo if self.journal.can_redo() => {
+                self.start_op(FileOp::Redo, ConflictPolicy::Ask);
+            }
             // AppEvent::Rename => {
             // let _ = rename_item(&item, &new_name).await;
             // }
             AppEvent::Refresh => {
-                self.diff.refresh().await?;
+                self.error = None;
+                self.start_scan();
             }
             _ => {} // ignore it (TODO: handle it)
         }
@@ -216,6 +703,55 @@ impl View for DirView {
         Ok(())
     }
 
+    fn tick(&mut self) {
+        self.poll_op();
+        self.poll_scan();
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> bool {
+        if let Some(confirmation) = self.confirmation.take() {
+            match key_event.code {
+                KeyCode::Char('y') => {
+                    self.start_op(confirmation.op, ConflictPolicy::Ask);
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {}
+                _ => self.confirmation = Some(confirmation),
+            }
+            return true;
+        }
+        let Some(conflict) = self.conflict.take() else {
+            return false;
+        };
+        if conflict.dir_in_the_way {
+            match key_event.code {
+                KeyCode::Char('y') => {
+                    let options = CopyOptions {
+                        on_conflict: ConflictPolicy::Overwrite,
+                        replace_dirs: true,
+                        ..self.copy_options.clone()
+                    };
+                    self.start_op_with(conflict.op, options);
+                }
+                KeyCode::Char('n') | KeyCode::Esc => {}
+                _ => self.conflict = Some(conflict),
+            }
+            return true;
+        }
+        let on_conflict = match key_event.code {
+            KeyCode::Char('o') => ConflictPolicy::Overwrite,
+            KeyCode::Char('u') => ConflictPolicy::OverwriteIfOlder,
+            KeyCode::Char('s') => ConflictPolicy::Skip,
+            KeyCode::Char('k') => ConflictPolicy::KeepBoth,
+            KeyCode::Esc => return true,
+            _ => {
+                self.conflict = Some(conflict);
+                return true;
+            }
+        };
+        self.start_op(conflict.op, on_conflict);
+        true
+    }
+
     fn current_diff_item(&self) -> Option<&DiffItem> {
         let table_state = self.table_state.borrow();
         let i = table_state.selected()?;
@@ -304,6 +840,7 @@ impl WidgetRef for DirView {
             Constraint::Length(10), // Left Size
             Constraint::Length(19), // Left Modified
             Constraint::Length(3),  // Indicator
+            Constraint::Length(6),  // Attributes
             Constraint::Min(10),    // Right Name
             Constraint::Length(10), // Right Size
             Constraint::Length(19), // Right Modified
@@ -318,17 +855,18 @@ impl WidgetRef for DirView {
             Style::default().bold(),
         );
         buf.set_string(
-            header_layout[4].x + 1,
-            header_layout[4].y,
+            header_layout[5].x + 1,
+            header_layout[5].y,
             &right_path,
             Style::default().bold(),
         );
 
         // Table
-        let header_cells =
-            ["Name", "Size", "Modified", "", "Name", "Size", "Modified"]
-                .into_iter()
-                .map(|h| Cell::from(h).style(Style::default().bold()));
+        let header_cells = [
+            "Name", "Size", "Modified", "", "Attr", "Name", "Size", "Modified",
+        ]
+        .into_iter()
+        .map(|h| Cell::from(h).style(Style::default().bold()));
         let header = Row::new(header_cells)
             .height(1)
             .style(Style::default().bg(Color::Rgb(70, 70, 70)));
@@ -338,11 +876,8 @@ impl WidgetRef for DirView {
 
             // Left item
             if let Some(left) = &item.left_item {
-                let mut name = left.name().to_string_lossy();
-                if left.is_dir() {
-                    name += path::MAIN_SEPARATOR_STR;
-                };
-                cells.push(Cell::from(name.into_owned()));
+                let name = display_name(left, self.diff.left_dir.as_ref());
+                cells.push(Cell::from(name));
                 cells.push(Cell::from(
                     left.metadata()
                         .as_ref()
@@ -361,14 +896,12 @@ impl WidgetRef for DirView {
 
             // Diff type indicator
             cells.push(Cell::from(indicator(item.diff_item_type)));
+            cells.push(Cell::from(attr_indicator(item.attr_diff)));
 
             // Right item
             if let Some(right) = &item.right_item {
-                let mut name = right.name().to_string_lossy();
-                if right.is_dir() {
-                    name += path::MAIN_SEPARATOR_STR;
-                };
-                cells.push(Cell::from(name.into_owned()));
+                let name = display_name(right, self.diff.right_dir.as_ref());
+                cells.push(Cell::from(name));
                 cells.push(Cell::from(
                     right
                         .metadata()
@@ -407,8 +940,65 @@ impl WidgetRef for DirView {
         );
 
         // Footer
-        let count = self.diff.items.len();
-        let footer_text = format!("{} items", count);
+        let footer_text = if let Some(op) = &self.op {
+            format!("{} … (Esc: abort)", op.op.description())
+        } else if let Some(scan) = &self.scan {
+            format!(
+                "Comparing … {} items scanned, {} read{} (Esc: abort)",
+                scan.items_scanned,
+                format_size(scan.bytes_read),
+                scan.last_dir.as_ref().map_or(String::new(), |dir| format!(
+                    " | {}",
+                    dir.display()
+                ))
+            )
+        } else if let Some(error) = &self.error {
+            format!("Error: {}", error)
+        } else {
+            format!("{} items", self.diff.items.len())
+        };
         Paragraph::new(footer_text).render(footer_area, buf);
+
+        if let Some(confirmation) = &self.confirmation {
+            let area = centered_rect(60, 30, area);
+            Clear.render(area, buf);
+            Paragraph::new(confirmation.text())
+                .centered()
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().title("Confirm"))
+                .render(area, buf);
+        }
+
+        if let Some(conflict) = &self.conflict {
+            let area = centered_rect(60, 30, area);
+            Clear.render(area, buf);
+            let (title, text) = if conflict.dir_in_the_way {
+                (
+                    "Replace directory",
+                    format!(
+                        "{}: the directory {} is in the way. Replace it \
+                         with all its content?\n\ny: replace | n: cancel",
+                        conflict.op.description(),
+                        conflict.path.display()
+                    ),
+                )
+            } else {
+                (
+                    "Destination exists",
+                    format!(
+                        "{}: {} already exists.\n\no: overwrite | u: \
+                         overwrite if older | s: skip | k: keep both | Esc: \
+                         cancel",
+                        conflict.op.description(),
+                        conflict.path.display()
+                    ),
+                )
+            };
+            Paragraph::new(text)
+                .centered()
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().title(title))
+                .render(area, buf);
+        }
     }
 }
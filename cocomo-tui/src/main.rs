@@ -75,8 +75,12 @@ pub(crate) mod textview;
 /// Shared behavior for interactive views.
 pub(crate) mod view;
 
+use std::io::{self, Write};
+
 use cmdargs::CmdLineArgs;
-use cocomo_core::{FSItem, FSItemType};
+use cocomo_core::{
+    DirDiff, FSItem, FSItemType, TextDiff, patch, unified_diff,
+};
 use color_eyre::Report;
 
 use crate::app::App;
@@ -161,12 +165,38 @@ async fn check_args(
     err_report.map_or_else(|| Ok((left_item, right_item)), Err)
 }
 
+/// Writes the differences between the left and right item as a unified diff
+/// respectively a multi-file patch to stdout.
+async fn write_patch(
+    args: CmdLineArgs,
+    left: &Option<FSItem>,
+    right: &Option<FSItem>,
+    context: usize,
+) -> Result<(), Report> {
+    let is_dir = left.as_ref().or(right.as_ref()).is_some_and(FSItem::is_dir);
+    let output = if is_dir {
+        let diff =
+            DirDiff::with_options(left, right, args.diff_options).await?;
+        patch(&diff, context, &args.text_options).await?
+    } else {
+        let diff =
+            TextDiff::with_options(left, right, args.text_options).await?;
+        unified_diff(&diff, context)
+    };
+    io::stdout().write_all(output.as_bytes())?;
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Report> {
     color_eyre::install()?;
     let args = CmdLineArgs::get();
     let (left, right) = check_args(&args).await?;
-    let mut app = App::new();
+    if let Some(context) = args.patch_context {
+        return write_patch(args, &left, &right, context).await;
+    }
+    let mut app =
+        App::new(args.diff_options, args.text_options, args.copy_options);
     app.new_view(&left, &right).await?;
     let terminal = ratatui::init();
     let result = app.run(terminal).await;
@@ -15,15 +15,20 @@
 use core::cell;
 use std::io;
 
-use cocomo_core::{FSItem, LineDiffType, TextDiff};
+use cocomo_core::{
+    DiffLine, DiffSide, FSItem, LineDiffType, TextDiff, TextDiffOptions,
+    TextEncoding,
+};
 use futures::executor::block_on;
 use ratatui::{
     buffer::Buffer,
+    crossterm::event::{KeyCode, KeyEvent},
     layout::{Constraint, Layout, Rect},
     style::{Color, Style},
-    text::Text,
+    text::{Line, Span, Text},
     widgets::{
-        Block, Cell, Row, StatefulWidget, Table, TableState, WidgetRef,
+        Block, Cell, Paragraph, Row, StatefulWidget, Table, TableState,
+        Widget, WidgetRef,
     },
 };
 
@@ -41,15 +46,20 @@ pub struct TextView {
     table_state: cell::RefCell<TableState>,
     /// The index of the currently selected chunk.
     current_chunk: usize,
+    /// The error reported by the last operation, if any.
+    error: Option<String>,
 }
 
 impl TextView {
-    /// Creates a new `FileView` for two text files.
+    /// Creates a new `TextView` for two text files, compared according to
+    /// `options`.
     pub async fn new(
         left_item: &Option<FSItem>,
         right_item: &Option<FSItem>,
+        options: TextDiffOptions,
     ) -> io::Result<Self> {
-        let file_diff = TextDiff::new(left_item, right_item).await?;
+        let file_diff =
+            TextDiff::with_options(left_item, right_item, options).await?;
         let mut table_state = TableState::default();
         if !file_diff.chunks.is_empty() {
             table_state.select(Some(0));
@@ -58,6 +68,7 @@ impl TextView {
             file_diff,
             table_state: cell::RefCell::new(table_state),
             current_chunk: 0,
+            error: None,
         })
     }
 
@@ -70,29 +81,145 @@ impl TextView {
             .sum()
     }
 
+    /// Makes the chunk at `chunk_idx`, or the last one if there are less
+    /// chunks, the current chunk.
+    fn select_chunk(&mut self, chunk_idx: usize) {
+        self.current_chunk =
+            chunk_idx.min(self.file_diff.chunks.len().saturating_sub(1));
+        let row_idx = self.first_row_of_chunk(self.current_chunk);
+        self.table_state.borrow_mut().select(Some(row_idx));
+    }
+
+    /// Applies `edit` to the current chunk, modifying the text on `side`.
+    ///
+    /// Unchanged chunks and sides without a file are left alone.
+    fn edit_chunk(
+        &mut self,
+        side: DiffSide,
+        edit: impl FnOnce(&mut TextDiff, usize),
+    ) {
+        let file = match side {
+            DiffSide::Left => &self.file_diff.left_file,
+            DiffSide::Right => &self.file_diff.right_file,
+        };
+        if file.name().is_empty() {
+            self.error = Some(format!(
+                "There is no {} file to modify.",
+                side_name(side)
+            ));
+            return;
+        }
+        if self
+            .file_diff
+            .chunks
+            .get(self.current_chunk)
+            .is_none_or(|chunk| chunk.diff_type == LineDiffType::Unchanged)
+        {
+            return;
+        }
+        self.error = None;
+        edit(&mut self.file_diff, self.current_chunk);
+        self.select_chunk(self.current_chunk);
+    }
+
+    /// Copies the lines of the current chunk from side `from` to the other
+    /// side.
+    fn copy_chunk(&mut self, from: DiffSide) {
+        let to = match from {
+            DiffSide::Left => DiffSide::Right,
+            DiffSide::Right => DiffSide::Left,
+        };
+        self.edit_chunk(to, |diff, idx| diff.copy_chunk(idx, from));
+    }
+
+    /// Saves the modified texts to their files.
+    async fn save(&mut self) {
+        for side in [DiffSide::Left, DiffSide::Right] {
+            if self.file_diff.is_modified(side)
+                && let Err(err) = self.file_diff.save(side).await
+            {
+                self.error = Some(err.to_string());
+            }
+        }
+    }
+
     pub(crate) async fn handle_app_event(
         &mut self,
         app_event: AppEvent,
     ) -> color_eyre::Result<()> {
-        match app_event {
-            // TODO: handle copy, move, delete events
-            AppEvent::Copy => {
-                todo!()
-            }
-            AppEvent::Move => {
-                todo!()
+        // Saving and refreshing don't need a current chunk; all chunks may
+        // have been deleted
+        let diff_type = self
+            .file_diff
+            .chunks
+            .get(self.current_chunk)
+            .map(|chunk| chunk.diff_type);
+        match (app_event, diff_type) {
+            (AppEvent::Copy, Some(diff_type)) => {
+                // Copy the lines to the side lacking them, changed lines
+                // from left to right
+                let from = match diff_type {
+                    LineDiffType::Added => DiffSide::Right,
+                    _ => DiffSide::Left,
+                };
+                self.copy_chunk(from);
             }
-            AppEvent::Delete => {
-                todo!()
+            (AppEvent::Trash | AppEvent::Delete, Some(diff_type)) => {
+                // Delete the lines on the right side, unless the chunk only
+                // exists on the left side
+                let side = match diff_type {
+                    LineDiffType::Removed => DiffSide::Left,
+                    _ => DiffSide::Right,
+                };
+                self.edit_chunk(side, |diff, idx| {
+                    diff.delete_chunk(idx, side);
+                });
             }
-            _ => Ok(()),
+            (AppEvent::Save, _) => self.save().await,
+            (AppEvent::Refresh, _) => match self.file_diff.refresh().await {
+                Ok(()) => {
+                    self.error = None;
+                    self.select_chunk(self.current_chunk);
+                }
+                Err(err) => self.error = Some(err.to_string()),
+            },
+            // Chunks can't be moved, only copied or deleted
+            _ => {}
         }
+        Ok(())
+    }
+}
+
+/// Returns the name of `side` for messages.
+const fn side_name(side: DiffSide) -> &'static str {
+    match side {
+        DiffSide::Left => "left",
+        DiffSide::Right => "right",
     }
 }
 
 impl View for TextView {
     fn title(&self) -> String {
-        self.file_diff.name().to_string_lossy().into_owned()
+        let name = self.file_diff.name().to_string_lossy();
+        if self.is_modified() {
+            format!("{name} *")
+        } else {
+            name.into_owned()
+        }
+    }
+
+    fn is_modified(&self) -> bool {
+        self.file_diff.is_modified(DiffSide::Left)
+            || self.file_diff.is_modified(DiffSide::Right)
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> bool {
+        match key_event.code {
+            KeyCode::Char('>') => self.copy_chunk(DiffSide::Left),
+            KeyCode::Char('<') => self.copy_chunk(DiffSide::Right),
+            _ => return false,
+        }
+        true
     }
 
     fn handle_app_event(
@@ -154,10 +281,39 @@ fn indicator<'a>(dt: LineDiffType) -> Text<'a> {
         .centered()
 }
 
+/// Returns the content of `line` without its line terminator, with its inline
+/// changes highlighted.
+fn line_content(line: &DiffLine) -> Line<'_> {
+    let content = line.content.trim_end_matches(['\n', '\r']);
+    let highlight = Style::default().bg(Color::Rgb(160, 120, 0));
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for range in &line.inline_changes {
+        let (start, end) =
+            (range.start.max(pos), range.end.min(content.len()));
+        if start >= end {
+            continue;
+        }
+        if start > pos {
+            spans.push(Span::raw(&content[pos..start]));
+        }
+        spans.push(Span::styled(&content[start..end], highlight));
+        pos = end;
+    }
+    if pos < content.len() {
+        spans.push(Span::raw(&content[pos..]));
+    }
+    Line::from(spans)
+}
+
 impl WidgetRef for TextView {
     fn render_ref(&self, area: Rect, buf: &mut Buffer) {
-        let vert_constraints = [Constraint::Length(1), Constraint::Min(0)];
-        let [header_area, content_area] =
+        let vert_constraints = [
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ];
+        let [header_area, content_area, footer_area] =
             Layout::vertical(vert_constraints).areas(area);
 
         let horiz_constraints = [
@@ -170,24 +326,31 @@ impl WidgetRef for TextView {
         let header_layout =
             Layout::horizontal(horiz_constraints).split(header_area);
 
-        let left_path = if self.file_diff.left_file.name().is_empty() {
-            String::new()
-        } else {
-            self.file_diff
-                .left_file
-                .path()
-                .to_string_lossy()
-                .to_string()
-        };
-        let right_path = if self.file_diff.right_file.name().is_empty() {
-            String::new()
-        } else {
-            self.file_diff
-                .right_file
-                .path()
-                .to_string_lossy()
-                .to_string()
+        let header = |file: &FSItem, encoding: TextEncoding, side| {
+            if file.name().is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "{} [{encoding}]{}",
+                    file.path().to_string_lossy(),
+                    if self.file_diff.is_modified(side) {
+                        " (modified)"
+                    } else {
+                        ""
+                    }
+                )
+            }
         };
+        let left_path = header(
+            &self.file_diff.left_file,
+            self.file_diff.left_encoding,
+            DiffSide::Left,
+        );
+        let right_path = header(
+            &self.file_diff.right_file,
+            self.file_diff.right_encoding,
+            DiffSide::Right,
+        );
 
         buf.set_string(
             header_layout[0].x,
@@ -230,14 +393,14 @@ impl WidgetRef for TextView {
                         left.line_number
                             .map_or(String::new(), |n| n.to_string()),
                     ),
-                    Cell::from(left.content.as_str()),
+                    Cell::from(line_content(left)),
                     Cell::from(indicator(chunk.diff_type)),
                     Cell::from(
                         right
                             .line_number
                             .map_or(String::new(), |n| n.to_string()),
                     ),
-                    Cell::from(right.content.as_str()),
+                    Cell::from(line_content(right)),
                 ];
                 rows.push(Row::new(cells).style(chunk_style));
             }
@@ -252,5 +415,22 @@ impl WidgetRef for TextView {
             buf,
             &mut *self.table_state.borrow_mut(),
         );
+
+        // Footer
+        let footer_text = if let Some(error) = &self.error {
+            format!("Error: {}", error)
+        } else {
+            let differences = self
+                .file_diff
+                .chunks
+                .iter()
+                .filter(|chunk| chunk.diff_type != LineDiffType::Unchanged)
+                .count();
+            format!(
+                "{differences} differences | c: copy chunk | >/<: copy to \
+                 right/left | d: delete chunk | ^S: save"
+            )
+        };
+        Paragraph::new(footer_text).render(footer_area, buf);
     }
 }
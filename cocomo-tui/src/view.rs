@@ -12,7 +12,7 @@
 use core::fmt::Debug;
 
 use cocomo_core::DiffItem;
-use ratatui::widgets::WidgetRef;
+use ratatui::{crossterm::event::KeyEvent, widgets::WidgetRef};
 
 use crate::appevent::AppEvent;
 
@@ -33,6 +33,21 @@ pub(crate) trait View: Debug + WidgetRef {
         true
     }
 
+    /// Updates the state of the view; called on every tick.
+    fn tick(&mut self) {}
+
+    /// Handles a key event before it gets mapped to an application event,
+    /// e.g. while the view shows a dialog. Returns `true` if the event has
+    /// been consumed.
+    fn handle_key_event(&mut self, _key_event: KeyEvent) -> bool {
+        false
+    }
+
+    /// Returns `true` if the view holds modifications not saved yet.
+    fn is_modified(&self) -> bool {
+        false
+    }
+
     /// Returns the current diff item, if any.
     fn current_diff_item(&self) -> Option<&DiffItem> {
         None